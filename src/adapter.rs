@@ -0,0 +1,699 @@
+use std::rc::Rc;
+use std::str;
+use futures::{future, Future};
+use futures::future::Loop;
+use hyper::Url;
+use openssl::hash::{hash2, MessageDigest};
+use serde_json;
+use tokio_core::reactor::Handle;
+use http::{self, Response};
+use probe::{self, Prober, Route};
+use profile::PortalRule;
+use vendor::{self, Fingerprint, Vendor};
+
+//redirects followed when fetching the login page
+const MAX_REDIRECTS: u32 = 3;
+
+//empty for portals that only want their terms accepted
+#[derive(Clone, Debug, Default)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+//a request the portal understands, sent by send()
+#[derive(Clone, Debug)]
+pub struct PortalRequest {
+    pub method: &'static str,
+    pub url: String,
+    pub form: Vec<(String, String)>,
+}
+
+impl PortalRequest {
+    fn new(method: &'static str, url: String, form: &[(&str, &str)]) -> PortalRequest {
+        PortalRequest {
+            method: method,
+            url: url,
+            form: form.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    LoggedIn,
+    LoggedOut,
+    Unknown,
+}
+
+//speaks to one product's portal. None means the product cannot be driven that way and
+//the login page, or probing, has to do
+pub trait Adapter {
+    fn vendor(&self) -> Vendor;
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest>;
+    fn logout(&self) -> Option<PortalRequest>;
+
+    fn status(&self) -> Option<PortalRequest> {
+        None
+    }
+
+    fn read_status(&self, _response: &Response) -> Status {
+        Status::Unknown
+    }
+}
+
+//the login page as found, with whatever could be made of it
+pub struct Portal {
+    pub auth_url: String,
+    //where the login page was fetched from, the only host credentials are sent to
+    pub host: String,
+    pub fingerprint: Option<Fingerprint>,
+    pub adapter: Option<Rc<Adapter>>,
+}
+
+//fetches the login page, following its redirects, and tells what product serves it
+pub fn identify(auth_url: &str, route: &Route, handle: &Handle) -> Box<Future<Item=Portal, Error=()>> {
+    type Step = Box<Future<Item=Loop<(String, String), (String, u32)>, Error=()>>;
+    let original = auth_url.to_string();
+    let route = route.clone();
+    let handle = handle.clone();
+
+    let page = future::loop_fn((original.clone(), 0), move |(url, hops)| -> Step {
+        Box::new(http::request("GET", &url, &route, None, &handle).then(move |result| {
+            let location = match result {
+                Ok(ref res) if res.is_redirection() && hops < MAX_REDIRECTS => {
+                    res.header("Location").and_then(|location| Url::parse(&url).ok()?.join(location).ok())
+                },
+                _ => None,
+            };
+            match (location, result) {
+                (Some(location), _) => Ok::<_, ()>(Loop::Continue((location.into_string(), hops + 1))),
+                (None, Ok(res)) => Ok(Loop::Break((url, String::from_utf8_lossy(&res.body).into_owned()))),
+                (None, Err(e)) => {
+                    println!("cannot fetch the login page {}: {}", url, e);
+                    Ok(Loop::Break((url, String::new())))
+                },
+            }
+        }))
+    });
+
+    Box::new(page.map(move |(url, page)| {
+        let host = Url::parse(&url).ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default();
+        //the first address carries the parameters, redirects often drop them
+        let found = vendor::fingerprint(&url, Some(&page)).map(|fingerprint| (url.clone(), fingerprint))
+            .or_else(|| vendor::fingerprint(&original, Some(&page)).map(|fingerprint| (original.clone(), fingerprint)));
+        let (adapter, fingerprint) = match found {
+            //addresses alone are too common to send credentials on, the page has to agree
+            Some((_, ref fingerprint)) if !fingerprint.page_confirmed => {
+                println!("{} portal by its address only ({}), not logging in without the page",
+                         fingerprint.vendor, fingerprint.evidence.join(", "));
+                (None, Some(fingerprint.clone()))
+            },
+            Some((portal_url, fingerprint)) => {
+                println!("{} portal: {}", fingerprint.vendor, fingerprint.evidence.join(", "));
+                let adapter = Url::parse(&portal_url).ok()
+                    .map(|portal_url| adapter_for(fingerprint.vendor, &portal_url, &page));
+                (adapter, Some(fingerprint))
+            },
+            None => (None, None),
+        };
+        Portal {
+            auth_url: original,
+            host: host,
+            fingerprint: fingerprint,
+            adapter: adapter,
+        }
+    }))
+}
+
+pub fn adapter_for(vendor: Vendor, url: &Url, page: &str) -> Rc<Adapter> {
+    match vendor {
+        Vendor::CoovaChilli => Rc::new(CoovaChilli::new(url)),
+        Vendor::CiscoWlc => Rc::new(CiscoWlc::new(url, page)),
+        Vendor::CiscoIse => Rc::new(CiscoIse),
+        Vendor::ArubaClearPass => Rc::new(Aruba::new(url)),
+        Vendor::Unifi => Rc::new(Unifi::new(url)),
+        Vendor::Meraki => Rc::new(Meraki::new(url)),
+        Vendor::Mikrotik => Rc::new(Mikrotik::new(url, page)),
+        Vendor::PfSense => Rc::new(PfSense::new(url)),
+        Vendor::OpenNds => Rc::new(OpenNds::new(url, page)),
+        Vendor::Fortinet => Rc::new(Fortinet::new(url, page)),
+    }
+}
+
+//a portal that takes the request answers with a page or a redirect, whether it let us
+//through is for the probes to tell
+pub fn send(request: &PortalRequest, route: &Route, handle: &Handle) -> Box<Future<Item=Response, Error=String>> {
+    let https = request.url.starts_with("https:");
+    Box::new(http::submit(request.method, &request.url, &request.form, route, handle)
+        .map_err(move |e| if https {
+            //controllers often keep their self-signed certificate, see http::request
+            format!("{}, a portal whose certificate is not trusted has to be logged in to from the login page", e)
+        } else {
+            e.to_string()
+        })
+        .and_then(|res| {
+            if res.is_success() || res.is_redirection() {
+                Ok(res)
+            } else {
+                Err(format!("the portal answered with status {}", res.status))
+            }
+        }))
+}
+
+//the login request, refused when it would take the credentials anywhere but the login
+//page's host. the redirect's parameters (switch_url, uamip, switchip, base_grant_url)
+//come from whoever sent the redirect and may name any host
+pub fn login_request(adapter: &Adapter, credentials: &Credentials, page_host: &str) -> Result<PortalRequest, String> {
+    let request = match adapter.login(credentials) {
+        Some(request) => request,
+        None => return Err(format!("{} portals can only be logged in to from the login page", adapter.vendor())),
+    };
+    let host = Url::parse(&request.url).ok().and_then(|url| url.host_str().map(|host| host.to_string()));
+    match host {
+        Some(ref host) if !page_host.is_empty() && host == page_host => Ok(request),
+        host => Err(format!("the portal asks to log in at {}, not at {} where its login page is, log in on the login page",
+                            host.unwrap_or_else(|| request.url.clone()), page_host)),
+    }
+}
+
+pub fn log_in(adapter: &Adapter, credentials: &Credentials, page_host: &str, route: &Route, handle: &Handle)
+              -> Box<Future<Item=(), Error=String>> {
+    match login_request(adapter, credentials, page_host) {
+        Ok(request) => Box::new(send(&request, route, handle).map(|_| ())),
+        Err(e) => Box::new(future::err(e)),
+    }
+}
+
+pub fn log_out(adapter: &Adapter, route: &Route, handle: &Handle) -> Box<Future<Item=(), Error=String>> {
+    match adapter.logout() {
+        Some(request) => Box::new(send(&request, route, handle).map(|_| ())),
+        None => Box::new(future::err(format!("{} portals offer no way to log out", adapter.vendor()))),
+    }
+}
+
+//asks the portal alongside the probes where it can tell. a portal saying we are out is
+//believed, one saying we are in is not, e.g. when its status page is an error page, so
+//the probes have to agree
+pub fn is_logged_in(adapter: Option<Rc<Adapter>>, prober: &Prober, test_url: &str, route: &Route, rule: PortalRule,
+                    handle: &Handle) -> Box<Future<Item=bool, Error=()>> {
+    let online = probe::is_online(prober, test_url, route, rule, handle);
+    let (adapter, request) = match adapter.and_then(|adapter| adapter.status().map(|request| (adapter, request))) {
+        Some(status) => status,
+        None => return online,
+    };
+
+    let status = send(&request, route, handle).then(move |result| {
+        let status = match result {
+            Ok(res) => adapter.read_status(&res),
+            Err(e) => {
+                println!("cannot get the {} portal status: {}", adapter.vendor(), e);
+                Status::Unknown
+            },
+        };
+        Ok::<_, ()>(status)
+    });
+    Box::new(status.join(online).map(|(status, online)| status != Status::LoggedOut && online))
+}
+
+fn param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs().find(|&(ref key, _)| key == name).map(|(_, value)| value.into_owned())
+}
+
+fn origin(url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}://{}:{}", url.scheme(), url.host_str().unwrap_or(""), port),
+        None => format!("{}://{}", url.scheme(), url.host_str().unwrap_or("")),
+    }
+}
+
+//the value of the input with the given name, e.g. <input type="hidden" name="magic" value="...">
+fn form_field(page: &str, name: &str) -> Option<String> {
+    let pos = page.find(&format!("name=\"{}\"", name)).or_else(|| page.find(&format!("name='{}'", name)))?;
+    let start = page[..pos].rfind('<')?;
+    let end = pos + page[pos..].find('>')?;
+    let tag = &page[start..end];
+    let value = tag.find("value=")? + "value=".len();
+    let quote = tag[value..].chars().next()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let value_end = tag[value + 1..].find(quote)?;
+    Some(tag[value + 1..value + 1 + value_end].to_string())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.chars().all(|ch| ch.is_digit(16)) {
+        return None;
+    }
+    let mut bytes = vec![];
+    let mut pos = 0;
+    while pos < hex.len() {
+        bytes.push(u8::from_str_radix(&hex[pos..pos + 2], 16).ok()?);
+        pos += 2;
+    }
+    Some(bytes)
+}
+
+//the CHAP response both CoovaChilli and Mikrotik check: md5 of the id, the password and the challenge
+fn chap_response(id: &[u8], password: &str, challenge: &[u8]) -> Option<String> {
+    let mut data = id.to_vec();
+    data.extend_from_slice(password.as_bytes());
+    data.extend_from_slice(challenge);
+    hash2(MessageDigest::md5(), &data).ok().map(|digest| to_hex(&digest))
+}
+
+struct CoovaChilli {
+    base: String,
+    challenge: Option<String>,
+    userurl: String,
+}
+
+impl CoovaChilli {
+    fn new(url: &Url) -> CoovaChilli {
+        //the redirect points at the UAM server, the gateway's own address is in the parameters
+        let base = match (param(url, "uamip"), param(url, "uamport")) {
+            (Some(ip), Some(port)) => format!("http://{}:{}", ip, port),
+            _ => origin(url),
+        };
+        CoovaChilli {
+            base: base,
+            challenge: param(url, "challenge"),
+            userurl: param(url, "userurl").unwrap_or_default(),
+        }
+    }
+}
+
+impl Adapter for CoovaChilli {
+    fn vendor(&self) -> Vendor {
+        Vendor::CoovaChilli
+    }
+
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        let challenge = from_hex(self.challenge.as_ref()?)?;
+        let response = chap_response(&[0], &credentials.password, &challenge)?;
+        Some(PortalRequest::new("GET", format!("{}/logon", self.base), &[
+            ("username", &credentials.username),
+            ("response", &response),
+            ("userurl", &self.userurl),
+        ]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("GET", format!("{}/logoff", self.base), &[]))
+    }
+
+    fn status(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("GET", format!("{}/json/status", self.base), &[]))
+    }
+
+    //{"clientState": 1, ...}, 1 being authorized
+    fn read_status(&self, response: &Response) -> Status {
+        let body = String::from_utf8_lossy(&response.body);
+        let json = match (body.find('{'), body.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &body[start..end + 1],
+            _ => return Status::Unknown,
+        };
+        match serde_json::from_str::<serde_json::Value>(json).ok().and_then(|status| status["clientState"].as_i64()) {
+            Some(1) => Status::LoggedIn,
+            Some(_) => Status::LoggedOut,
+            None => Status::Unknown,
+        }
+    }
+}
+
+struct CiscoWlc {
+    switch_url: String,
+    redirect: String,
+    base: String,
+}
+
+impl CiscoWlc {
+    fn new(url: &Url, page: &str) -> CiscoWlc {
+        CiscoWlc {
+            switch_url: param(url, "switch_url").unwrap_or_else(|| format!("{}/login.html", origin(url))),
+            redirect: param(url, "redirect").or_else(|| form_field(page, "redirect_url")).unwrap_or_default(),
+            base: origin(url),
+        }
+    }
+}
+
+impl Adapter for CiscoWlc {
+    fn vendor(&self) -> Vendor {
+        Vendor::CiscoWlc
+    }
+
+    //buttonClicked 4 is the submit button of both the web auth and the passthrough pages
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        Some(PortalRequest::new("POST", self.switch_url.clone(), &[
+            ("buttonClicked", "4"),
+            ("err_flag", "0"),
+            ("err_msg", ""),
+            ("info_flag", "0"),
+            ("info_msg", ""),
+            ("redirect_url", &self.redirect),
+            ("username", &credentials.username),
+            ("password", &credentials.password),
+        ]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("POST", format!("{}/logout.html", self.base), &[
+            ("userStatus", "1"),
+            ("err_flag", "0"),
+            ("err_msg", ""),
+        ]))
+    }
+}
+
+//the guest flows run in the page's scripts
+struct CiscoIse;
+
+impl Adapter for CiscoIse {
+    fn vendor(&self) -> Vendor {
+        Vendor::CiscoIse
+    }
+
+    fn login(&self, _credentials: &Credentials) -> Option<PortalRequest> {
+        None
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        None
+    }
+}
+
+struct Aruba {
+    base: String,
+    url: String,
+}
+
+impl Aruba {
+    fn new(url: &Url) -> Aruba {
+        //a ClearPass guest page posts back to the controller it came from
+        let base = match param(url, "switchip") {
+            Some(ref switchip) if !url.host_str().unwrap_or("").contains("securelogin") => format!("https://{}", switchip),
+            _ => origin(url),
+        };
+        Aruba {
+            base: base,
+            url: param(url, "url").unwrap_or_default(),
+        }
+    }
+}
+
+impl Adapter for Aruba {
+    fn vendor(&self) -> Vendor {
+        Vendor::ArubaClearPass
+    }
+
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        Some(PortalRequest::new("POST", format!("{}/cgi-bin/login", self.base), &[
+            ("user", &credentials.username),
+            ("password", &credentials.password),
+            ("cmd", "authenticate"),
+            ("url", &self.url),
+        ]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("GET", format!("{}/cgi-bin/login", self.base), &[("cmd", "logout")]))
+    }
+}
+
+struct Unifi {
+    base: String,
+    site: String,
+}
+
+impl Unifi {
+    fn new(url: &Url) -> Unifi {
+        //the path is /guest/s/<site>/
+        let site = url.path_segments()
+            .and_then(|mut segments| segments.nth(2).map(|site| site.to_string()))
+            .unwrap_or_else(|| "default".to_string());
+        Unifi {
+            base: origin(url),
+            site: site,
+        }
+    }
+}
+
+impl Adapter for Unifi {
+    fn vendor(&self) -> Vendor {
+        Vendor::Unifi
+    }
+
+    //a password portal takes the password alone, an open one just the terms
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        let url = format!("{}/guest/s/{}/login", self.base, self.site);
+        if credentials.password.is_empty() {
+            Some(PortalRequest::new("POST", url, &[("by", "none")]))
+        } else {
+            Some(PortalRequest::new("POST", url, &[("by", "password"), ("password", &credentials.password)]))
+        }
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        None
+    }
+}
+
+struct Meraki {
+    grant_url: String,
+    continue_url: String,
+}
+
+impl Meraki {
+    fn new(url: &Url) -> Meraki {
+        Meraki {
+            grant_url: param(url, "base_grant_url").unwrap_or_else(|| format!("{}/splash/grant", origin(url))),
+            continue_url: param(url, "user_continue_url").or_else(|| param(url, "continue_url")).unwrap_or_default(),
+        }
+    }
+}
+
+impl Adapter for Meraki {
+    fn vendor(&self) -> Vendor {
+        Vendor::Meraki
+    }
+
+    //only the click-through splash is granted this way, sign-on splashes check the
+    //account in the page
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        if !credentials.username.is_empty() {
+            return None;
+        }
+        Some(PortalRequest::new("GET", self.grant_url.clone(), &[("continue_url", &self.continue_url)]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        None
+    }
+}
+
+struct Mikrotik {
+    base: String,
+    dst: String,
+    //from the page's hexMD5('<id>' + password + '<challenge>')
+    chap: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Mikrotik {
+    fn new(url: &Url, page: &str) -> Mikrotik {
+        Mikrotik {
+            base: origin(url),
+            dst: param(url, "dst").unwrap_or_default(),
+            chap: chap_challenge(page),
+        }
+    }
+}
+
+//the id and challenge are string literals with octal escapes
+fn chap_challenge(page: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let start = page.find("hexMD5(")? + "hexMD5(".len();
+    let call = &page[start..start + page[start..].find(')')?];
+    //'<id>' + document.login.password.value + '<challenge>'
+    let parts: Vec<&str> = call.split('\'').collect();
+    if parts.len() < 5 {
+        return None;
+    }
+    Some((unescape_octal(parts[1])?, unescape_octal(parts[3])?))
+}
+
+fn unescape_octal(literal: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut rest = literal.as_bytes();
+    while !rest.is_empty() {
+        if rest[0] == b'\\' && rest.len() >= 4 {
+            let octal = str::from_utf8(&rest[1..4]).ok()?;
+            bytes.push(u8::from_str_radix(octal, 8).ok()?);
+            rest = &rest[4..];
+        } else {
+            bytes.push(rest[0]);
+            rest = &rest[1..];
+        }
+    }
+    Some(bytes)
+}
+
+impl Adapter for Mikrotik {
+    fn vendor(&self) -> Vendor {
+        Vendor::Mikrotik
+    }
+
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        let password = match self.chap {
+            Some((ref id, ref challenge)) => chap_response(id, &credentials.password, challenge)?,
+            None => credentials.password.clone(),
+        };
+        Some(PortalRequest::new("POST", format!("{}/login", self.base), &[
+            ("username", &credentials.username),
+            ("password", &password),
+            ("dst", &self.dst),
+            ("popup", "false"),
+        ]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("GET", format!("{}/logout", self.base), &[]))
+    }
+
+    fn status(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("GET", format!("{}/status", self.base), &[]))
+    }
+
+    //the status page sends clients that are not logged in to the login page
+    fn read_status(&self, response: &Response) -> Status {
+        if response.is_redirection() {
+            Status::LoggedOut
+        } else if String::from_utf8_lossy(&response.body).contains("name=\"login\"") {
+            Status::LoggedOut
+        } else {
+            Status::LoggedIn
+        }
+    }
+}
+
+struct PfSense {
+    base: String,
+    zone: String,
+    redirurl: String,
+}
+
+impl PfSense {
+    fn new(url: &Url) -> PfSense {
+        PfSense {
+            base: origin(url),
+            zone: param(url, "zone").unwrap_or_default(),
+            redirurl: param(url, "redirurl").unwrap_or_default(),
+        }
+    }
+}
+
+impl Adapter for PfSense {
+    fn vendor(&self) -> Vendor {
+        Vendor::PfSense
+    }
+
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        let mut url = Url::parse(&format!("{}/index.php", self.base)).ok()?;
+        url.query_pairs_mut().append_pair("zone", &self.zone);
+        Some(PortalRequest::new("POST", url.into_string(), &[
+            ("auth_user", &credentials.username),
+            ("auth_pass", &credentials.password),
+            ("redirurl", &self.redirurl),
+            ("zone", &self.zone),
+            ("accept", "Login"),
+        ]))
+    }
+
+    //logging out needs the id from the logout popup shown after logging in
+    fn logout(&self) -> Option<PortalRequest> {
+        None
+    }
+}
+
+struct OpenNds {
+    base: String,
+    token: Option<String>,
+    redir: String,
+    //nodogsplash, which openNDS grew out of, names its endpoints after itself
+    legacy: bool,
+}
+
+impl OpenNds {
+    fn new(url: &Url, page: &str) -> OpenNds {
+        OpenNds {
+            base: origin(url),
+            token: param(url, "tok").or_else(|| form_field(page, "tok")),
+            redir: param(url, "redir").or_else(|| form_field(page, "redir")).unwrap_or_default(),
+            legacy: url.path().starts_with("/nodogsplash") || page.contains("nodogsplash"),
+        }
+    }
+
+    fn endpoint(&self, name: &str) -> String {
+        format!("{}/{}_{}/", self.base, if self.legacy { "nodogsplash" } else { "opennds" }, name)
+    }
+}
+
+impl Adapter for OpenNds {
+    fn vendor(&self) -> Vendor {
+        Vendor::OpenNds
+    }
+
+    fn login(&self, _credentials: &Credentials) -> Option<PortalRequest> {
+        let token = self.token.as_ref()?;
+        Some(PortalRequest::new("GET", self.endpoint("auth"), &[("tok", token), ("redir", &self.redir)]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        let name = if self.legacy { "deauth" } else { "deny" };
+        Some(PortalRequest::new("GET", self.endpoint(name), &[]))
+    }
+}
+
+struct Fortinet {
+    base: String,
+    magic: Option<String>,
+    redirect: String,
+}
+
+impl Fortinet {
+    fn new(url: &Url, page: &str) -> Fortinet {
+        //the magic comes as the bare query of /fgtauth?<magic> and again in the form
+        let magic = form_field(page, "magic")
+            .or_else(|| url.query().and_then(|query| if query.contains('=') { None } else { Some(query.to_string()) }));
+        Fortinet {
+            base: origin(url),
+            magic: magic,
+            redirect: form_field(page, "4Tredir").unwrap_or_default(),
+        }
+    }
+}
+
+impl Adapter for Fortinet {
+    fn vendor(&self) -> Vendor {
+        Vendor::Fortinet
+    }
+
+    fn login(&self, credentials: &Credentials) -> Option<PortalRequest> {
+        let magic = self.magic.as_ref()?;
+        Some(PortalRequest::new("POST", format!("{}/", self.base), &[
+            ("4Tredir", &self.redirect),
+            ("magic", magic),
+            ("username", &credentials.username),
+            ("password", &credentials.password),
+        ]))
+    }
+
+    fn logout(&self) -> Option<PortalRequest> {
+        Some(PortalRequest::new("GET", format!("{}/logout", self.base), &[]))
+    }
+}
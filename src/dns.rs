@@ -0,0 +1,253 @@
+use std::ffi::{CStr, CString};
+use std::io;
+use std::net::{self, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Command;
+use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::vec;
+use futures::{future, Future};
+use futures::future::Loop;
+use futures::sync::oneshot;
+use gio_sys;
+use glib_sys;
+use gobject_sys;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio_core::net::UdpSocket;
+use tokio_core::reactor::Handle;
+use interface;
+use reactor;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const TIMEOUT_SECS: u64 = 2;
+
+//the name servers the interface got from DHCP (or RA), as NetworkManager reports them
+pub fn dhcp_servers(interface: &str) -> Vec<IpAddr> {
+    let output = match Command::new("nmcli").args(&["-g", "IP4.DNS,IP6.DNS", "device", "show", interface]).output() {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+        Ok(output) => {
+            println!("nmcli failed: {}", String::from_utf8_lossy(&output.stderr));
+            return vec![];
+        },
+        Err(e) => {
+            println!("could not run nmcli: {}", e);
+            return vec![];
+        },
+    };
+
+    //one line per field, several servers in a field are separated by " | "
+    output.split(|ch: char| ch == '\n' || ch == '|')
+        .filter_map(|server| server.trim().replace("\\:", ":").parse().ok())
+        .collect()
+}
+
+//asks the servers directly, or the system resolver when none are given. literal
+//addresses are returned as they are
+pub fn resolve(name: &str, servers: &[IpAddr], interface: Option<&str>, handle: &Handle)
+               -> Box<Future<Item=Vec<IpAddr>, Error=io::Error>> {
+    if let Ok(addr) = name.trim_matches(|ch: char| ch == '[' || ch == ']').parse::<IpAddr>() {
+        return Box::new(future::ok(vec![addr]));
+    }
+    if servers.is_empty() {
+        return resolve_system(name);
+    }
+    let servers = servers.to_vec();
+    let name = name.to_string();
+    let interface = interface.map(|interface| interface.to_string());
+    let handle = handle.clone();
+
+    type Step = Box<Future<Item=Loop<Vec<IpAddr>, (vec::IntoIter<IpAddr>, io::Error)>, Error=io::Error>>;
+    let no_servers = io::Error::new(io::ErrorKind::NotFound, "no name servers");
+    Box::new(future::loop_fn((servers.into_iter(), no_servers), move |(mut servers, last_err)| -> Step {
+        let server = match servers.next() {
+            Some(server) => server,
+            None => return Box::new(future::err(last_err)),
+        };
+        let interface = interface.as_ref().map(|s| s.as_str());
+        let queries = query(&name, TYPE_A, &server, interface, &handle)
+            .then(|result| Ok::<_, io::Error>(result))
+            .join(query(&name, TYPE_AAAA, &server, interface, &handle).then(|result| Ok::<_, io::Error>(result)));
+        Box::new(queries.map(move |(a, aaaa)| {
+            let mut last_err = last_err;
+            let mut addrs = vec![];
+            for answers in vec![a, aaaa] {
+                match answers {
+                    Ok(answers) => addrs.extend(answers),
+                    Err(e) => last_err = e,
+                }
+            }
+            if addrs.is_empty() {
+                Loop::Continue((servers, last_err))
+            } else {
+                Loop::Break(addrs)
+            }
+        }))
+    }))
+}
+
+//getaddrinfo through GIO, so /etc/hosts, search domains and nsswitch apply as they do
+//for every other program. GIO runs it in its own worker and answers on the main loop
+fn resolve_system(name: &str) -> Box<Future<Item=Vec<IpAddr>, Error=io::Error>> {
+    let c_name = match CString::new(name) {
+        Ok(c_name) => c_name,
+        Err(e) => return Box::new(future::err(io::Error::new(io::ErrorKind::InvalidInput, e))),
+    };
+    let (tx, rx) = oneshot::channel::<io::Result<Vec<IpAddr>>>();
+    unsafe {
+        let resolver = gio_sys::g_resolver_get_default();
+        let data = Box::into_raw(Box::new(tx)) as glib_sys::gpointer;
+        gio_sys::g_resolver_lookup_by_name_async(resolver, c_name.as_ptr(), ptr::null_mut(), Some(resolved), data);
+        gobject_sys::g_object_unref(resolver as *mut gobject_sys::GObject);
+    }
+    let name = name.to_string();
+    Box::new(rx.then(move |result| match result {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::Other, format!("looking up {} was dropped", name))),
+    }))
+}
+
+unsafe extern "C" fn resolved(source: *mut gobject_sys::GObject, result: *mut gio_sys::GAsyncResult,
+                              data: glib_sys::gpointer) {
+    let tx = Box::from_raw(data as *mut oneshot::Sender<io::Result<Vec<IpAddr>>>);
+    let mut error: *mut glib_sys::GError = ptr::null_mut();
+    let list = gio_sys::g_resolver_lookup_by_name_finish(source as *mut gio_sys::GResolver, result, &mut error);
+    if !error.is_null() {
+        let message = CStr::from_ptr((*error).message).to_string_lossy().into_owned();
+        glib_sys::g_error_free(error);
+        tx.send(Err(io::Error::new(io::ErrorKind::NotFound, message))).ok();
+        return;
+    }
+
+    let mut addrs = vec![];
+    let mut node = list;
+    while !node.is_null() {
+        let text = gio_sys::g_inet_address_to_string((*node).data as *mut gio_sys::GInetAddress);
+        if let Ok(addr) = CStr::from_ptr(text).to_string_lossy().parse() {
+            addrs.push(addr);
+        }
+        glib_sys::g_free(text as glib_sys::gpointer);
+        node = (*node).next;
+    }
+    gio_sys::g_resolver_free_addresses(list);
+    tx.send(Ok(addrs)).ok();
+}
+
+fn query(name: &str, qtype: u16, server: &IpAddr, interface: Option<&str>, handle: &Handle)
+         -> Box<Future<Item=Vec<IpAddr>, Error=io::Error>> {
+    let server = SocketAddr::new(*server, 53);
+    let id = query_id();
+    let socket = build_query(id, name, qtype)
+        .and_then(|packet| udp_socket(&server, interface).map(|socket| (socket, packet)))
+        .and_then(|(socket, packet)| UdpSocket::from_socket(socket, handle).map(|socket| (socket, packet)));
+    let (socket, packet) = match socket {
+        Ok(socket) => socket,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    let answers = socket.send_dgram(packet, server)
+        .and_then(move |(socket, _)| {
+            future::loop_fn(socket, move |socket| {
+                socket.recv_dgram(vec![0u8; 1500]).and_then(move |(socket, buf, len, from)| {
+                    //anything else is late or spoofed
+                    if from != server || len < 2 || read_u16(&buf, 0) != Some(id) {
+                        return Ok(Loop::Continue(socket));
+                    }
+                    parse_answers(&buf[..len], qtype)
+                        .map(Loop::Break)
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed dns response"))
+                })
+            })
+        });
+    reactor::timeout(answers, Duration::from_secs(TIMEOUT_SECS), handle)
+}
+
+fn udp_socket(server: &SocketAddr, interface: Option<&str>) -> io::Result<net::UdpSocket> {
+    let domain = if server.is_ipv4() { Domain::ipv4() } else { Domain::ipv6() };
+    let socket = Socket::new(domain, Type::dgram(), Some(Protocol::udp()))?;
+    //a local stub resolver cannot be reached from inside an interface
+    let interface = interface.and_then(|interface| if server.ip().is_loopback() { None } else { Some(interface) });
+    if let Some(interface) = interface {
+        interface::bind(&socket, server, interface)?;
+    } else {
+        let any = if server.is_ipv4() { IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)) } else { IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)) };
+        socket.bind(&SockAddr::from(SocketAddr::new(any, 0)))?;
+    }
+    Ok(socket.into_udp_socket())
+}
+
+fn query_id() -> u16 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|now| now.subsec_nanos() as u16)
+        .unwrap_or(0x4361)
+}
+
+fn build_query(id: u16, name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(512);
+    packet.extend_from_slice(&[(id >> 8) as u8, id as u8]);
+    //recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in name.trim_right_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid name {}", name)));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&[(qtype >> 8) as u8, qtype as u8, (CLASS_IN >> 8) as u8, CLASS_IN as u8]);
+    Ok(packet)
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    if pos + 2 > buf.len() {
+        return None;
+    }
+    Some((buf[pos] as u16) << 8 | buf[pos + 1] as u16)
+}
+
+//the position after the (possibly compressed) name starting at pos
+fn skip_name(buf: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(pos)? as usize;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += len + 1;
+    }
+}
+
+fn parse_answers(buf: &[u8], qtype: u16) -> Option<Vec<IpAddr>> {
+    let questions = read_u16(buf, 4)?;
+    let answers = read_u16(buf, 6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(buf, pos)? + 4;
+    }
+
+    let mut addrs = vec![];
+    for _ in 0..answers {
+        pos = skip_name(buf, pos)?;
+        let rtype = read_u16(buf, pos)?;
+        let rlen = read_u16(buf, pos + 8)? as usize;
+        pos += 10;
+        let data = buf.get(pos..pos + rlen)?;
+        pos += rlen;
+        //CNAMEs are followed by the server already
+        match (rtype, rlen) {
+            (TYPE_A, 4) if qtype == TYPE_A => {
+                addrs.push(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])));
+            },
+            (TYPE_AAAA, 16) if qtype == TYPE_AAAA => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(data);
+                addrs.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            },
+            _ => {},
+        }
+    }
+    Some(addrs)
+}
@@ -0,0 +1,368 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use futures::{future, Future};
+use gtk::{
+    self, WidgetExt, ContainerExt, BoxExt, ButtonExt, LabelExt, DialogExt, EntryExt,
+    FileChooserExt, GridExt, ComboBoxExt, ComboBoxTextExt, Continue,
+};
+use http::{self, Response};
+use probe::Route;
+use reactor;
+use servo::ipc_channel::ipc::IpcSender;
+use servo::servo_url::ServoUrl;
+
+//onboarding files offered by enterprise portals and eduroam CAT
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    Certificate,
+    Pkcs12,
+    AppleProfile,
+    EapConfig,
+    Installer,
+}
+
+impl FileKind {
+    //whether NetworkManager can use the file for an 802.1X connection
+    fn can_setup_network(&self) -> bool {
+        match *self {
+            FileKind::Certificate | FileKind::Pkcs12 => true,
+            _ => false,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            FileKind::Certificate => "CA certificate",
+            FileKind::Pkcs12 => "client certificate",
+            FileKind::AppleProfile => "configuration profile",
+            FileKind::EapConfig => "EAP configuration",
+            FileKind::Installer => "installer",
+        }
+    }
+}
+
+pub fn kind_of(url: &ServoUrl) -> Option<FileKind> {
+    //eduroam CAT hands out installers from its API rather than from files
+    if url.path().ends_with("/API.php") &&
+        url.as_url().query_pairs().any(|(key, value)| key == "action" && value == "downloadInstaller") {
+        return Some(FileKind::Installer);
+    }
+    kind_of_name(url.path())
+}
+
+fn kind_of_name(name: &str) -> Option<FileKind> {
+    let name = name.to_lowercase();
+    let extension = match name.rfind('.') {
+        Some(pos) => &name[pos + 1..],
+        None => return None,
+    };
+    match extension {
+        "crt" | "cer" | "der" | "pem" => Some(FileKind::Certificate),
+        "p12" | "pfx" => Some(FileKind::Pkcs12),
+        "mobileconfig" => Some(FileKind::AppleProfile),
+        "eap-config" => Some(FileKind::EapConfig),
+        "py" | "sh" | "run" | "exe" | "msi" | "deb" | "rpm" => Some(FileKind::Installer),
+        _ => None,
+    }
+}
+
+//the server knows better than the extension when it says so
+fn kind_of_mime(mime: &str) -> Option<FileKind> {
+    match mime {
+        "application/x-x509-ca-cert" | "application/x-x509-user-cert" |
+        "application/pkix-cert" | "application/x-pem-file" => Some(FileKind::Certificate),
+        "application/x-pkcs12" => Some(FileKind::Pkcs12),
+        "application/x-apple-aspen-config" => Some(FileKind::AppleProfile),
+        "application/eap-config" => Some(FileKind::EapConfig),
+        _ => None,
+    }
+}
+
+fn mime_of(res: &Response) -> Option<String> {
+    res.header("Content-Type")
+        .and_then(|value| value.split(';').next())
+        .map(|mime| mime.trim().to_lowercase())
+}
+
+//the file name from an attachment's Content-Disposition
+fn attachment_name(res: &Response) -> Option<String> {
+    let disposition = res.header("Content-Disposition")?;
+    let mut parts = disposition.split(';').map(|part| part.trim());
+    if parts.next().map_or(true, |kind| kind.to_lowercase() != "attachment") {
+        return None;
+    }
+    parts.filter_map(|part| {
+        let pos = part.find('=')?;
+        if part[..pos].trim().to_lowercase() != "filename" {
+            return None;
+        }
+        Some(part[pos + 1..].trim().trim_matches('"').to_string())
+    }).find(|name| !name.is_empty())
+}
+
+//whether the navigation leads to an onboarding file. only addresses that look like one
+//are asked about, every other navigation goes on untouched so a GET with side effects
+//such as a login is never sent twice. servo keeps its cookies to itself, so only what
+//this bare request already gets as a file is taken from servo
+fn inspect(url: &ServoUrl, route: &Route) -> Box<Future<Item=Option<(FileKind, Option<String>)>, Error=()>> {
+    let by_address = match kind_of(url) {
+        Some(kind) => kind,
+        None => return Box::new(future::ok(None)),
+    };
+    let head = http::request("HEAD", url.as_str(), route, None, &reactor::handle());
+    Box::new(head.then(move |result| {
+        let res = match result {
+            Ok(res) => res,
+            //unanswered, the address decides
+            Err(_) => return Ok(Some((by_address, None))),
+        };
+        match res.status {
+            200 => {},
+            //servers without HEAD are judged by the address
+            405 | 501 => return Ok(Some((by_address, None))),
+            _ => return Ok(None),
+        }
+        let mime = mime_of(&res);
+        //a page at a file's address, the server wants the portal's session for it
+        if mime.as_ref().map_or(false, |mime| mime == "text/html") {
+            return Ok(None);
+        }
+        let name = attachment_name(&res);
+        let kind = mime.as_ref().and_then(|mime| kind_of_mime(mime))
+            .or_else(|| name.as_ref().and_then(|name| kind_of_name(name)))
+            .unwrap_or(by_address);
+        Ok(Some((kind, name)))
+    }))
+}
+
+#[derive(Clone, Debug)]
+enum DownloadState {
+    Finished(FileKind),
+    Failed(String),
+}
+
+struct Row {
+    status: gtk::Label,
+    setup_button: gtk::Button,
+}
+
+impl Row {
+    fn update(&self, state: DownloadState) {
+        match state {
+            DownloadState::Finished(kind) => {
+                self.status.set_text(&format!("Saved {}", kind.description()));
+                self.setup_button.set_visible(kind.can_setup_network());
+            },
+            DownloadState::Failed(reason) => self.status.set_text(&format!("Failed: {}", reason)),
+        }
+    }
+}
+
+//saves files the page would navigate to and lists them in the downloads popover
+pub struct Downloads {
+    parent: gtk::ApplicationWindow,
+    button: gtk::MenuButton,
+    list: gtk::ListBox,
+    rows: Rc<RefCell<Vec<Row>>>,
+}
+
+impl Downloads {
+    pub fn new(builder: &gtk::Builder) -> Rc<Downloads> {
+        Rc::new(Downloads {
+            parent: builder.get_object("window").unwrap(),
+            button: builder.get_object("downloads-button").unwrap(),
+            list: builder.get_object("downloads-list").unwrap(),
+            rows: Rc::new(RefCell::new(vec![])),
+        })
+    }
+
+    //answers servo whether to go on with a navigation, taking downloads away from it.
+    //the question is asked through the window's route, as the probes do
+    pub fn check(downloads: &Rc<Downloads>, url: ServoUrl, route: &Route, chan: IpcSender<bool>) {
+        let downloads = downloads.clone();
+        let inspected = inspect(&url, route);
+        let route = route.clone();
+        reactor::handle().spawn(inspected.map(move |download| {
+            match download {
+                Some((kind, name)) => {
+                    chan.send(false).ok();
+                    //the save dialog runs a main loop of its own, not from inside the reactor
+                    let mut offer = Some((url, kind, name, route));
+                    gtk::idle_add(move || {
+                        if let Some((url, kind, name, route)) = offer.take() {
+                            downloads.offer(url, kind, name, route);
+                        }
+                        Continue(false)
+                    });
+                },
+                None => {
+                    chan.send(true).ok();
+                },
+            }
+        }));
+    }
+
+    //asks where to save the file, then downloads it in the background
+    fn offer(&self, url: ServoUrl, kind: FileKind, name: Option<String>, route: Route) {
+        let name = name.unwrap_or_else(|| {
+            url.path_segments()
+                .and_then(|segments| segments.last())
+                .and_then(|name| if name.is_empty() { None } else { Some(name) })
+                .unwrap_or("download")
+                .to_string()
+        });
+
+        let dialog = gtk::FileChooserDialog::new(
+            Some(&format!("Save {}", kind.description())), Some(&self.parent), gtk::FileChooserAction::Save);
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel.into());
+        dialog.add_button("Save", gtk::ResponseType::Accept.into());
+        dialog.set_current_name(&name);
+        dialog.set_do_overwrite_confirmation(true);
+        let response = dialog.run();
+        let path = dialog.get_filename();
+        dialog.destroy();
+        let path = match path {
+            Some(ref path) if response == gtk::ResponseType::Accept.into() => path.clone(),
+            _ => return,
+        };
+
+        let index = self.add_row(&name, &path, kind);
+        let rows = self.rows.clone();
+        reactor::handle().spawn(fetch(&url, path, &route).then(move |result| {
+            let state = match result {
+                Ok(mime) => DownloadState::Finished(mime.and_then(|mime| kind_of_mime(&mime)).unwrap_or(kind)),
+                Err(e) => DownloadState::Failed(e.to_string()),
+            };
+            rows.borrow()[index].update(state);
+            Ok(())
+        }));
+    }
+
+    fn add_row(&self, name: &str, path: &Path, kind: FileKind) -> usize {
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        let labels = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        let name_label = gtk::Label::new(Some(name));
+        name_label.set_halign(gtk::Align::Start);
+        let status = gtk::Label::new(Some("Downloading..."));
+        status.set_halign(gtk::Align::Start);
+        labels.pack_start(&name_label, false, false, 0);
+        labels.pack_start(&status, false, false, 0);
+        row_box.pack_start(&labels, true, true, 0);
+
+        let setup_button = gtk::Button::new_with_label("Set up network");
+        setup_button.set_no_show_all(true);
+        {
+            let parent = self.parent.clone();
+            let path = path.to_path_buf();
+            setup_button.connect_clicked(move |_| {
+                setup_network(&parent, &path, kind);
+            });
+        }
+        row_box.pack_start(&setup_button, false, false, 0);
+
+        self.list.add(&row_box);
+        row_box.show_all();
+        self.button.set_visible(true);
+
+        println!("downloading to {:?}", path);
+        let mut rows = self.rows.borrow_mut();
+        rows.push(Row {
+            status: status,
+            setup_button: setup_button,
+        });
+        rows.len() - 1
+    }
+}
+
+//saves the body and returns the content type the server reported
+fn fetch(url: &ServoUrl, path: PathBuf, route: &Route) -> Box<Future<Item=Option<String>, Error=io::Error>> {
+    Box::new(http::download(url.as_str(), route, &reactor::handle()).and_then(move |res| {
+        if res.status != 200 {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("status {}", res.status)));
+        }
+        let mime = mime_of(&res);
+        //a login page in place of the file, the server wanted the portal's session after all
+        if mime.as_ref().map_or(false, |mime| mime == "text/html") {
+            return Err(io::Error::new(io::ErrorKind::Other, "the server sent a page instead of the file"));
+        }
+        let mut file = File::create(&path)?;
+        file.write_all(&res.body)?;
+        Ok(mime)
+    }))
+}
+
+//hands the certificate to NetworkManager as an 802.1X Wi-Fi connection
+fn setup_network(parent: &gtk::ApplicationWindow, path: &Path, kind: FileKind) {
+    let dialog = gtk::Dialog::new_with_buttons(
+        Some("Set up network"), Some(parent), gtk::DIALOG_MODAL,
+        &[("Cancel", gtk::ResponseType::Cancel.into()),
+          ("Create connection", gtk::ResponseType::Accept.into())]);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(6);
+    grid.set_border_width(12);
+
+    let ssid = gtk::Entry::new();
+    ssid.set_text("eduroam");
+    let identity = gtk::Entry::new();
+    let eap = gtk::ComboBoxText::new();
+    match kind {
+        FileKind::Pkcs12 => eap.append_text("tls"),
+        _ => {
+            eap.append_text("peap");
+            eap.append_text("ttls");
+        },
+    }
+    eap.set_active(0);
+
+    grid.attach(&gtk::Label::new(Some("Network name")), 0, 0, 1, 1);
+    grid.attach(&ssid, 1, 0, 1, 1);
+    grid.attach(&gtk::Label::new(Some("Identity")), 0, 1, 1, 1);
+    grid.attach(&identity, 1, 1, 1, 1);
+    grid.attach(&gtk::Label::new(Some("EAP method")), 0, 2, 1, 1);
+    grid.attach(&eap, 1, 2, 1, 1);
+    dialog.get_content_area().add(&grid);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let ssid = ssid.get_text().unwrap_or_default();
+    let identity = identity.get_text().unwrap_or_default();
+    let eap = eap.get_active_text().unwrap_or_default();
+    dialog.destroy();
+    if response != gtk::ResponseType::Accept.into() || ssid.is_empty() {
+        return;
+    }
+
+    let path = path.to_string_lossy().into_owned();
+    let mut nmcli = Command::new("nmcli");
+    nmcli.args(&["connection", "add", "type", "wifi", "ifname", "*"])
+        .args(&["con-name", &ssid, "ssid", &ssid])
+        .args(&["wifi-sec.key-mgmt", "wpa-eap"])
+        .args(&["802-1x.eap", &eap, "802-1x.identity", &identity]);
+    match kind {
+        FileKind::Pkcs12 => {
+            //the bundle holds both the certificate and the key, whose password is asked for
+            //by NetworkManager when connecting
+            nmcli.args(&["802-1x.client-cert", &path, "802-1x.private-key", &path,
+                         "802-1x.private-key-password-flags", "2"]);
+        },
+        _ => {
+            nmcli.args(&["802-1x.ca-cert", &path, "802-1x.phase2-auth", "mschapv2"]);
+        },
+    }
+
+    let message = match nmcli.output() {
+        Ok(ref output) if output.status.success() => format!("Created the connection \"{}\".", ssid),
+        Ok(output) => format!("nmcli failed: {}", String::from_utf8_lossy(&output.stderr)),
+        Err(e) => format!("Could not run nmcli: {}", e),
+    };
+    let info = gtk::MessageDialog::new(
+        Some(parent), gtk::DIALOG_MODAL, gtk::MessageType::Info, gtk::ButtonsType::Close, &message);
+    info.run();
+    info.destroy();
+}
@@ -0,0 +1,233 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem;
+use servo;
+use servo::compositing::windowing::{WindowEvent, WindowMethods};
+use servo::script_traits::TouchEventType;
+use servo::webrender_api::ScrollLocation;
+
+//rounds of queued events delivered per wake, anything left waits for the next one
+const MAX_ROUNDS: usize = 8;
+
+//anything that consumes window events, servo or a recorder in tests
+pub trait EventSink {
+    fn handle_events(&mut self, events: Vec<WindowEvent>);
+}
+
+impl<W: WindowMethods + 'static> EventSink for servo::Servo<W> {
+    fn handle_events(&mut self, events: Vec<WindowEvent>) {
+        servo::Servo::handle_events(self, events);
+    }
+}
+
+//window events waiting to be delivered in FIFO order
+pub struct EventQueue {
+    events: VecDeque<WindowEvent>,
+}
+
+impl EventQueue {
+    pub fn new() -> EventQueue {
+        EventQueue {
+            events: VecDeque::new(),
+        }
+    }
+
+    //merges the event into the last queued one when only the latest state matters
+    pub fn push(&mut self, event: WindowEvent) {
+        let coalesced = match (self.events.back_mut(), &event) {
+            (Some(&mut WindowEvent::MouseWindowMoveEventClass(ref mut last)),
+             &WindowEvent::MouseWindowMoveEventClass(point)) => {
+                *last = point;
+                true
+            },
+            (Some(&mut WindowEvent::Scroll(ScrollLocation::Delta(ref mut last_delta),
+                                           ref mut last_origin, TouchEventType::Move)),
+             &WindowEvent::Scroll(ScrollLocation::Delta(delta), origin, TouchEventType::Move)) => {
+                *last_delta = *last_delta + delta;
+                *last_origin = origin;
+                true
+            },
+            _ => false,
+        };
+        if !coalesced {
+            self.events.push_back(event);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn drain(&mut self) -> Vec<WindowEvent> {
+        mem::replace(&mut self.events, VecDeque::new()).into_iter().collect()
+    }
+}
+
+//delivers everything queued, including events queued by callbacks made while delivering.
+//returns whether events are still waiting after the last round, the caller wakes again for them
+pub fn pump<S: EventSink>(queue: &RefCell<EventQueue>, sink: &mut S) -> bool {
+    let events = queue.borrow_mut().drain();
+    sink.handle_events(events);
+
+    for _ in 0..MAX_ROUNDS {
+        if queue.borrow().is_empty() {
+            break;
+        }
+        let events = queue.borrow_mut().drain();
+        sink.handle_events(events);
+    }
+    !queue.borrow().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use servo::compositing::windowing::WindowEvent;
+    use servo::euclid::{TypedPoint2D, TypedVector2D};
+    use servo::script_traits::TouchEventType;
+    use servo::webrender_api::ScrollLocation;
+    use super::{EventQueue, EventSink, MAX_ROUNDS, pump};
+
+    struct Recorder {
+        batches: Vec<Vec<WindowEvent>>,
+        queue: Rc<RefCell<EventQueue>>,
+        //events queued from inside handle_events, like WindowMethods callbacks do
+        reentrant: Vec<WindowEvent>,
+    }
+
+    impl EventSink for Recorder {
+        fn handle_events(&mut self, events: Vec<WindowEvent>) {
+            self.batches.push(events);
+            for event in self.reentrant.drain(..) {
+                self.queue.borrow_mut().push(event);
+            }
+        }
+    }
+
+    //queues another event for every batch, so the queue never empties
+    struct Echo {
+        batches: usize,
+        queue: Rc<RefCell<EventQueue>>,
+    }
+
+    impl EventSink for Echo {
+        fn handle_events(&mut self, _events: Vec<WindowEvent>) {
+            self.batches += 1;
+            self.queue.borrow_mut().push(WindowEvent::Refresh);
+        }
+    }
+
+    fn scroll(dy: f32, phase: TouchEventType) -> WindowEvent {
+        let delta = ScrollLocation::Delta(TypedVector2D::new(0.0, dy));
+        WindowEvent::Scroll(delta, TypedPoint2D::new(0, 0), phase)
+    }
+
+    fn mouse_move(x: f32) -> WindowEvent {
+        WindowEvent::MouseWindowMoveEventClass(TypedPoint2D::new(x, 0.0))
+    }
+
+    #[test]
+    fn drains_in_fifo_order() {
+        let mut queue = EventQueue::new();
+        queue.push(WindowEvent::Refresh);
+        queue.push(WindowEvent::Zoom(2.0));
+        queue.push(WindowEvent::ResetZoom);
+
+        let events = queue.drain();
+        assert_eq!(events.len(), 3);
+        match (&events[0], &events[1], &events[2]) {
+            (&WindowEvent::Refresh, &WindowEvent::Zoom(_), &WindowEvent::ResetZoom) => {},
+            _ => panic!("unexpected order {:?}", events),
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn coalesces_mouse_moves() {
+        let mut queue = EventQueue::new();
+        queue.push(mouse_move(1.0));
+        queue.push(mouse_move(2.0));
+        queue.push(mouse_move(3.0));
+
+        let events = queue.drain();
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            WindowEvent::MouseWindowMoveEventClass(point) => assert_eq!(point.x, 3.0),
+            _ => panic!("unexpected event {:?}", events[0]),
+        }
+    }
+
+    #[test]
+    fn coalesces_scroll_moves_only() {
+        let mut queue = EventQueue::new();
+        queue.push(scroll(-10.0, TouchEventType::Down));
+        queue.push(scroll(-10.0, TouchEventType::Move));
+        queue.push(scroll(-20.0, TouchEventType::Move));
+        queue.push(scroll(0.0, TouchEventType::Up));
+
+        let events = queue.drain();
+        assert_eq!(events.len(), 3);
+        match events[1] {
+            WindowEvent::Scroll(ScrollLocation::Delta(delta), _, TouchEventType::Move) => {
+                assert_eq!(delta.y, -30.0)
+            },
+            _ => panic!("unexpected event {:?}", events[1]),
+        }
+    }
+
+    #[test]
+    fn keeps_order_across_other_events() {
+        let mut queue = EventQueue::new();
+        queue.push(mouse_move(1.0));
+        queue.push(WindowEvent::Refresh);
+        queue.push(mouse_move(2.0));
+
+        assert_eq!(queue.drain().len(), 3);
+    }
+
+    #[test]
+    fn pump_delivers_reentrant_events() {
+        let queue = Rc::new(RefCell::new(EventQueue::new()));
+        queue.borrow_mut().push(WindowEvent::Refresh);
+        queue.borrow_mut().push(WindowEvent::ResetZoom);
+
+        let mut recorder = Recorder {
+            batches: vec![],
+            queue: queue.clone(),
+            reentrant: vec![WindowEvent::Zoom(1.5)],
+        };
+        assert!(!pump(&queue, &mut recorder));
+
+        assert_eq!(recorder.batches.len(), 2);
+        assert_eq!(recorder.batches[0].len(), 2);
+        assert_eq!(recorder.batches[1].len(), 1);
+        assert!(queue.borrow().is_empty());
+    }
+
+    #[test]
+    fn pump_always_gives_servo_a_turn() {
+        let queue = Rc::new(RefCell::new(EventQueue::new()));
+        let mut recorder = Recorder {
+            batches: vec![],
+            queue: queue.clone(),
+            reentrant: vec![],
+        };
+        pump(&queue, &mut recorder);
+
+        assert_eq!(recorder.batches.len(), 1);
+        assert!(recorder.batches[0].is_empty());
+    }
+
+    #[test]
+    fn pump_reports_events_left_after_the_last_round() {
+        let queue = Rc::new(RefCell::new(EventQueue::new()));
+        let mut echo = Echo {
+            batches: 0,
+            queue: queue.clone(),
+        };
+        assert!(pump(&queue, &mut echo));
+        assert_eq!(echo.batches, MAX_ROUNDS + 1);
+        assert!(!queue.borrow().is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use std::cell::RefCell;
+use std::ptr;
+use std::rc::Rc;
+use std::time::Duration;
+use futures::Future;
+use glib_sys;
+use tokio_core::reactor::Core;
+use cafcon::adapter;
+use cafcon::probe::{self, HttpProber, Route};
+use cafcon::profile::{Profile, PortalRule};
+
+//how long the reactor waits before GLib gets a turn
+const GLIB_INTERVAL_MILLIS: u64 = 10;
+
+//like Core::run, but GLib's default main context is turned as well. the system resolver
+//and proxy lookups answer there, and no GTK main loop runs without a window
+fn run<F>(core: &mut Core, future: F) -> Result<F::Item, F::Error>
+    where F: Future + 'static, F::Item: 'static, F::Error: 'static
+{
+    let result = Rc::new(RefCell::new(None));
+    {
+        let result = result.clone();
+        core.handle().spawn(future.then(move |outcome| {
+            *result.borrow_mut() = Some(outcome);
+            Ok(())
+        }));
+    }
+    loop {
+        core.turn(Some(Duration::from_millis(GLIB_INTERVAL_MILLIS)));
+        unsafe {
+            while glib_sys::g_main_context_iteration(ptr::null_mut(), glib_sys::GFALSE) != glib_sys::GFALSE {}
+        }
+        if let Some(outcome) = result.borrow_mut().take() {
+            return outcome;
+        }
+    }
+}
+
+//without a window, for portals whose product is known, e.g. from a network dispatcher
+//script. returns whether the network is open afterwards
+pub fn log_in(test_url: &str, interface: Option<&str>, direct: bool, rule: PortalRule) -> bool {
+    let mut core = match Core::new() {
+        Ok(core) => core,
+        Err(e) => {
+            println!("cannot create the reactor: {}", e);
+            return false;
+        },
+    };
+    let handle = core.handle();
+
+    let detection = match run(&mut core, probe::detect(&HttpProber, test_url, interface, direct, rule, &handle)) {
+        Ok(detection) => detection,
+        Err(failure) => {
+            println!("{}", failure.message);
+            return false;
+        },
+    };
+    let portal = match run(&mut core, adapter::identify(&detection.auth_url, &detection.route, &handle)) {
+        Ok(portal) => portal,
+        Err(()) => return false,
+    };
+    let adapter = match portal.adapter {
+        Some(adapter) => adapter,
+        None => {
+            println!("the portal at {} is not a known product, log in on the login page", portal.auth_url);
+            return false;
+        },
+    };
+
+    let credentials = Profile::load().credentials(&portal.host);
+    if let Err(e) = run(&mut core, adapter::log_in(&*adapter, &credentials, &portal.host, &detection.route, &handle)) {
+        println!("logging in to the {} portal failed: {}", adapter.vendor(), e);
+        return false;
+    }
+    match run(&mut core, adapter::is_logged_in(Some(adapter.clone()), &HttpProber, test_url, &detection.route, rule, &handle)) {
+        Ok(true) => {
+            println!("logged in to the {} portal at {}", adapter.vendor(), portal.auth_url);
+            true
+        },
+        _ => {
+            println!("the {} portal took the login but the network is still closed", adapter.vendor());
+            false
+        },
+    }
+}
+
+//the login page is needed to know whom to tell, detection cannot find it once logged in
+pub fn log_out(auth_url: &str, interface: Option<&str>) -> bool {
+    let mut core = match Core::new() {
+        Ok(core) => core,
+        Err(e) => {
+            println!("cannot create the reactor: {}", e);
+            return false;
+        },
+    };
+    let handle = core.handle();
+    let route = Route::system(interface);
+
+    let adapter = match run(&mut core, adapter::identify(auth_url, &route, &handle)) {
+        Ok(adapter::Portal { adapter: Some(adapter), .. }) => adapter,
+        _ => {
+            println!("the portal at {} is not a known product", auth_url);
+            return false;
+        },
+    };
+    match run(&mut core, adapter::log_out(&*adapter, &route, &handle)) {
+        Ok(()) => {
+            println!("logged out of the {} portal", adapter.vendor());
+            true
+        },
+        Err(e) => {
+            println!("logging out of the {} portal failed: {}", adapter.vendor(), e);
+            false
+        },
+    }
+}
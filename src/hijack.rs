@@ -0,0 +1,91 @@
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use futures::{future, Future};
+use libc;
+use tokio_core::reactor::Handle;
+use dns;
+use probe::Route;
+
+//names whose addresses are not going to change
+const KNOWN_NAMES: &[(&str, &[&str])] = &[
+    ("one.one.one.one", &["1.1.1.1", "1.0.0.1", "2606:4700:4700::1111", "2606:4700:4700::1001"]),
+    ("dns.google", &["8.8.8.8", "8.8.4.4", "2001:4860:4860::8888", "2001:4860:4860::8844"]),
+    ("resolver1.opendns.com", &["208.67.222.222", "2620:119:35::35"]),
+];
+
+//example.com has no wildcard record, so this must not resolve
+const NONEXISTENT_DOMAIN: &str = "example.com";
+
+//how strongly the DNS answers suggest a portal that answers every query itself
+pub struct DnsSignal {
+    pub score: f32,
+    //where the hijacked names point to, likely the portal
+    pub gateway: Option<IpAddr>,
+    pub reasons: Vec<String>,
+}
+
+//asks the route's name servers, all names at once
+pub fn detect(route: &Route, handle: &Handle) -> Box<Future<Item=DnsSignal, Error=()>> {
+    let nonexistent = nonexistent_name();
+    let mut names = vec![nonexistent.clone()];
+    names.extend(KNOWN_NAMES.iter().map(|&(name, _)| name.to_string()));
+
+    let interface = route.interface.as_ref().map(|s| s.as_str());
+    //a name that does not resolve is an empty answer, not a failure
+    let lookups: Vec<_> = names.iter()
+        .map(|name| {
+            dns::resolve(name, &route.dns_servers, interface, handle)
+                .then(|result| Ok::<_, ()>(result.unwrap_or_default()))
+        })
+        .collect();
+    Box::new(future::join_all(lookups).map(move |answers: Vec<Vec<IpAddr>>| weigh(&nonexistent, answers)))
+}
+
+//the answers come in the order of the nonexistent name followed by KNOWN_NAMES
+fn weigh(nonexistent: &str, answers: Vec<Vec<IpAddr>>) -> DnsSignal {
+    let mut signal = DnsSignal {
+        score: 0.0,
+        gateway: None,
+        reasons: vec![],
+    };
+    let mut hijacked_addrs = vec![];
+    let mut answers = answers.into_iter();
+
+    let nonexistent_answers = answers.next().unwrap_or_default();
+    if let Some(&addr) = nonexistent_answers.first() {
+        signal.score += 0.5;
+        signal.gateway = Some(addr);
+        signal.reasons.push(format!("nonexistent {} resolved to {}", nonexistent, addr));
+        hijacked_addrs.extend(nonexistent_answers);
+    }
+
+    for (&(name, expected), answers) in KNOWN_NAMES.iter().zip(answers) {
+        let expected: Vec<IpAddr> = expected.iter().filter_map(|addr| addr.parse().ok()).collect();
+        if answers.is_empty() || answers.iter().any(|addr| expected.contains(addr)) {
+            continue;
+        }
+        signal.score += 0.2;
+        signal.gateway = signal.gateway.or(answers.first().cloned());
+        signal.reasons.push(format!("{} resolved to {}", name, answers[0]));
+        hijacked_addrs.extend(answers);
+    }
+
+    //every name pointing to the same single host is what a hijacking portal does
+    if hijacked_addrs.len() > 1 && hijacked_addrs.iter().all(|addr| *addr == hijacked_addrs[0]) {
+        signal.score += 0.2;
+        signal.reasons.push(format!("all names resolved to {}", hijacked_addrs[0]));
+    }
+
+    if signal.score > 1.0 {
+        signal.score = 1.0;
+    }
+    signal
+}
+
+fn nonexistent_name() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|now| now.subsec_nanos())
+        .unwrap_or(0);
+    let pid = unsafe { libc::getpid() };
+    format!("cafcon-{:08x}{:08x}.{}", pid, nanos, NONEXISTENT_DOMAIN)
+}
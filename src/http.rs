@@ -0,0 +1,310 @@
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::str;
+use std::time::Duration;
+use futures::{future, Future};
+use futures::future::Loop;
+use hyper::Url;
+use openssl::ssl::{SslConnectorBuilder, SslMethod};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_io::io::{read, read_to_end, write_all};
+use tokio_openssl::SslConnectorExt;
+use url::form_urlencoded;
+use dns;
+use interface::{self, Family};
+use probe::Route;
+use proxy::{self, Proxy};
+use reactor;
+
+pub const READ_TIMEOUT_SECS: u64 = 5;
+//portal pages are small, anything past this is not needed to tell what they are
+const MAX_RESPONSE_BYTES: u64 = 256 * 1024;
+//onboarding files are certificates, profiles and small installers
+const MAX_DOWNLOAD_BYTES: u64 = 32 * 1024 * 1024;
+const DOWNLOAD_TIMEOUT_SECS: u64 = 120;
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&&(ref key, _)| key.to_lowercase() == name.to_lowercase())
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        self.status >= 300 && self.status < 400
+    }
+}
+
+//resolves the host and connects through the route, trying its addresses in turn
+pub fn connect(host: &str, port: u16, route: &Route, family: Option<Family>, handle: &Handle)
+               -> Box<Future<Item=TcpStream, Error=io::Error>> {
+    type Step = Box<Future<Item=Loop<TcpStream, (Vec<SocketAddr>, io::Error)>, Error=io::Error>>;
+    let interface = route.interface.clone();
+    let handle = handle.clone();
+    let not_found = io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", host));
+
+    let addrs = dns::resolve(host, &route.dns_servers, route.interface.as_ref().map(|s| s.as_str()), &handle);
+    Box::new(addrs.and_then(move |addrs| {
+        let mut addrs: Vec<SocketAddr> = addrs.into_iter()
+            .map(|addr| SocketAddr::new(addr, port))
+            .filter(|addr| family.map_or(true, |family| family.matches(addr)))
+            .collect();
+        addrs.reverse();
+        future::loop_fn((addrs, not_found), move |(mut addrs, last_err)| -> Step {
+            let addr = match addrs.pop() {
+                Some(addr) => addr,
+                None => return Box::new(future::err(last_err)),
+            };
+            Box::new(interface::connect(&addr, interface.as_ref().map(|s| s.as_str()), &handle).then(move |result| {
+                match result {
+                    Ok(stream) => Ok::<_, io::Error>(Loop::Break(stream)),
+                    Err(e) => Ok(Loop::Continue((addrs, e))),
+                }
+            }))
+        })
+    }))
+}
+
+//connects to the server of the url, through the proxy on system routes. https is
+//tunnelled with CONNECT, for http the second value tells that the request goes to the
+//proxy and so names the whole url. direct routes skip proxies
+pub fn connect_to(url: &Url, route: &Route, family: Option<Family>, handle: &Handle)
+                  -> Box<Future<Item=(TcpStream, bool), Error=io::Error>> {
+    let https = url.scheme() == "https";
+    let host = url.host_str().unwrap_or("").to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let proxy: Box<Future<Item=Proxy, Error=io::Error>> = if route.dns_servers.is_empty() {
+        proxy::lookup_async(url.as_str())
+    } else {
+        Box::new(future::ok(Proxy::Direct))
+    };
+
+    let route = route.clone();
+    let handle = handle.clone();
+    Box::new(proxy.and_then(move |proxy| -> Box<Future<Item=(TcpStream, bool), Error=io::Error>> {
+        match proxy {
+            Proxy::Direct => Box::new(connect(&host, port, &route, family, &handle).map(|stream| (stream, false))),
+            Proxy::Http(proxy_host, proxy_port) => {
+                let stream = connect(&proxy_host, proxy_port, &route, family, &handle);
+                if https {
+                    Box::new(stream.and_then(move |stream| tunnel(stream, &host, port)).map(|stream| (stream, false)))
+                } else {
+                    Box::new(stream.map(|stream| (stream, true)))
+                }
+            },
+        }
+    }))
+}
+
+//asks the proxy for a connection to host:port, nothing is read past its answer as
+//the server says nothing before the TLS client does
+fn tunnel(stream: TcpStream, host: &str, port: u16) -> Box<Future<Item=TcpStream, Error=io::Error>> {
+    type Step = Box<Future<Item=Loop<(TcpStream, Vec<u8>), (TcpStream, Vec<u8>)>, Error=io::Error>>;
+    let request = format!("CONNECT {}:{} HTTP/1.1\r\nHost: {}:{}\r\nUser-Agent: cafcon\r\n\r\n", host, port, host, port);
+    let answer = write_all(stream, request.into_bytes()).and_then(|(stream, _)| {
+        future::loop_fn((stream, vec![]), |(stream, head)| -> Step {
+            Box::new(read(stream, vec![0u8; 1024]).and_then(move |(stream, buf, len)| {
+                if len == 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the proxy closed the tunnel"));
+                }
+                let mut head = head;
+                head.extend_from_slice(&buf[..len]);
+                if head.windows(4).any(|window| window == b"\r\n\r\n") {
+                    Ok(Loop::Break((stream, head)))
+                } else if head.len() as u64 > MAX_RESPONSE_BYTES {
+                    Err(io::Error::new(io::ErrorKind::InvalidData, "malformed proxy response"))
+                } else {
+                    Ok(Loop::Continue((stream, head)))
+                }
+            }))
+        })
+    });
+    Box::new(answer.and_then(|(stream, head)| {
+        let response = parse_response(&head)?;
+        if !response.is_success() {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("the proxy refused the tunnel with {}", response.status)));
+        }
+        Ok(stream)
+    }))
+}
+
+//hyper 0.10 blocks a thread per request and can neither bind to an interface nor ask
+//the route's name servers, so probes and downloads speak this little HTTP themselves.
+//a single HTTP/1.1 exchange without following redirects. https is verified against the
+//system's CAs, so controllers with self-signed certificates cannot be reached
+pub fn request(method: &str, url: &str, route: &Route, family: Option<Family>, handle: &Handle)
+               -> Box<Future<Item=Response, Error=io::Error>> {
+    exchange(method, url, None, route, family, handle)
+}
+
+//a GET for a file, allowed to be larger and to take longer than a page
+pub fn download(url: &str, route: &Route, handle: &Handle) -> Box<Future<Item=Response, Error=io::Error>> {
+    //room for the head on top of the file
+    let limits = Limits {
+        bytes: MAX_DOWNLOAD_BYTES + MAX_RESPONSE_BYTES,
+        timeout: Duration::from_secs(DOWNLOAD_TIMEOUT_SECS),
+    };
+    Box::new(exchange_within("GET", url, None, route, None, limits, handle).and_then(|res| {
+        if res.body.len() as u64 > MAX_DOWNLOAD_BYTES {
+            return Err(io::Error::new(io::ErrorKind::Other, "the file is too large"));
+        }
+        let length = res.header("Content-Length").and_then(|length| length.parse::<usize>().ok());
+        if length.map_or(false, |length| res.body.len() < length) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the file was cut off"));
+        }
+        Ok(res)
+    }))
+}
+
+//sends a form, in the body of a POST and in the query of anything else
+pub fn submit(method: &str, url: &str, form: &[(String, String)], route: &Route, handle: &Handle)
+              -> Box<Future<Item=Response, Error=io::Error>> {
+    if method == "POST" {
+        let body = form_urlencoded::Serializer::new(String::new()).extend_pairs(form).finish();
+        return exchange(method, url, Some(body), route, None, handle);
+    }
+    match Url::parse(url) {
+        Ok(mut url) => {
+            if !form.is_empty() {
+                url.query_pairs_mut().extend_pairs(form);
+            }
+            exchange(method, url.as_str(), None, route, None, handle)
+        },
+        Err(e) => Box::new(future::err(io::Error::new(io::ErrorKind::InvalidInput, e))),
+    }
+}
+
+struct Limits {
+    bytes: u64,
+    timeout: Duration,
+}
+
+fn exchange(method: &str, url: &str, body: Option<String>, route: &Route, family: Option<Family>, handle: &Handle)
+            -> Box<Future<Item=Response, Error=io::Error>> {
+    let limits = Limits {
+        bytes: MAX_RESPONSE_BYTES,
+        timeout: Duration::from_secs(interface::CONNECT_TIMEOUT_SECS + READ_TIMEOUT_SECS),
+    };
+    exchange_within(method, url, body, route, family, limits, handle)
+}
+
+fn exchange_within(method: &str, url: &str, body: Option<String>, route: &Route, family: Option<Family>,
+                   limits: Limits, handle: &Handle) -> Box<Future<Item=Response, Error=io::Error>> {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(e) => return Box::new(future::err(io::Error::new(io::ErrorKind::InvalidInput, e))),
+    };
+    let https = match url.scheme() {
+        "http" => false,
+        "https" => true,
+        scheme => return Box::new(future::err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                             format!("{} is not supported", scheme)))),
+    };
+    let host = url.host_str().unwrap_or("").to_string();
+
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.clone(),
+    };
+
+    let method = method.to_string();
+    let max_bytes = limits.bytes;
+    let stream = connect_to(&url, route, family, handle);
+    let data = stream.and_then(move |(stream, absolute)| -> Box<Future<Item=Vec<u8>, Error=io::Error>> {
+        let target = if absolute { url.as_str().to_string() } else { path };
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: cafcon\r\nAccept: */*\r\nConnection: close\r\n",
+                                  method, target, host_header);
+        match body {
+            Some(body) => {
+                request.push_str(&format!("Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n",
+                                          body.len()));
+                request.push_str(&body);
+            },
+            None => request.push_str("\r\n"),
+        }
+
+        if !https {
+            return send_on(stream, request.into_bytes(), max_bytes);
+        }
+        let connector = match SslConnectorBuilder::new(SslMethod::tls()) {
+            Ok(builder) => builder.build(),
+            Err(e) => return Box::new(future::err(io::Error::new(io::ErrorKind::Other, e))),
+        };
+        Box::new(connector.connect_async(&host, stream)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+            .and_then(move |stream| send_on(stream, request.into_bytes(), max_bytes)))
+    });
+    let response = data.and_then(|data| parse_response(&data));
+    reactor::timeout(response, limits.timeout, handle)
+}
+
+fn send_on<S: AsyncRead + AsyncWrite + 'static>(stream: S, request: Vec<u8>, max_bytes: u64)
+                                                -> Box<Future<Item=Vec<u8>, Error=io::Error>> {
+    Box::new(write_all(stream, request)
+        .and_then(move |(stream, _)| read_to_end(stream.take(max_bytes), vec![]))
+        .map(|(_, data)| data))
+}
+
+fn parse_response(data: &[u8]) -> io::Result<Response> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed http response");
+    let end = data.windows(4).position(|window| window == b"\r\n\r\n").ok_or_else(&invalid)?;
+    let head = str::from_utf8(&data[..end]).map_err(|_| invalid())?;
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().ok_or_else(&invalid)?;
+    let status = status_line.split_whitespace().nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(&invalid)?;
+    let headers = lines
+        .filter_map(|line| {
+            line.find(':').map(|pos| (line[..pos].trim().to_string(), line[pos + 1..].trim().to_string()))
+        })
+        .collect();
+
+    let mut response = Response {
+        status: status,
+        headers: headers,
+        body: vec![],
+    };
+    let chunked = response.header("Transfer-Encoding").map_or(false, |coding| coding.to_lowercase().contains("chunked"));
+    response.body = if chunked { dechunk(&data[end + 4..]) } else { data[end + 4..].to_vec() };
+    Ok(response)
+}
+
+//whatever arrived of a chunked body, a cut off one is kept up to the cut
+fn dechunk(mut data: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    while let Some(line_end) = data.windows(2).position(|window| window == b"\r\n") {
+        let size = str::from_utf8(&data[..line_end]).ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next().unwrap_or("").trim(), 16).ok());
+        let size = match size {
+            Some(size) if size > 0 => size,
+            _ => break,
+        };
+        data = &data[line_end + 2..];
+        let len = if size < data.len() { size } else { data.len() };
+        body.extend_from_slice(&data[..len]);
+        if len + 2 > data.len() {
+            break;
+        }
+        data = &data[len + 2..];
+    }
+    body
+}
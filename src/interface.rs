@@ -0,0 +1,199 @@
+use std::ffi::{CStr, CString};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+use futures::{future, Future};
+use libc;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use reactor;
+
+//black-holing networks would otherwise keep a connect waiting for minutes
+pub const CONNECT_TIMEOUT_SECS: u64 = 5;
+
+//interfaces that are up and could lead to a portal, loopback excluded
+pub fn up_interfaces() -> Vec<String> {
+    let entries = match fs::read_dir("/sys/class/net") {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("cannot list interfaces: {}", e);
+            return vec![];
+        },
+    };
+
+    let mut interfaces: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name != "lo")
+        .filter(|name| read_sys(name, "operstate").map_or(false, |state| state == "up"))
+        .collect();
+    interfaces.sort();
+    interfaces
+}
+
+//tunnels of VPNs, which would carry the probes past the portal
+pub fn is_vpn(interface: &str) -> bool {
+    const PREFIXES: &[&str] = &["tun", "tap", "wg", "ppp", "vpn", "ipsec", "utun"];
+    //ARPHRD_PPP and ARPHRD_NONE, the latter used by tun and wireguard
+    PREFIXES.iter().any(|prefix| interface.starts_with(prefix)) ||
+        read_sys(interface, "tun_flags").is_some() ||
+        read_sys(interface, "type").map_or(false, |kind| kind == "512" || kind == "65534")
+}
+
+//the interface a portal can be on when the caller did not choose one: the one with the
+//default route, or with the next one when a VPN has taken it
+pub fn physical_interface() -> Option<String> {
+    let mut defaults: Vec<Ipv4Route> = ipv4_routes().into_iter()
+        .filter(|route| route.mask == 0 && !is_vpn(&route.interface))
+        .collect();
+    defaults.sort_by_key(|route| route.metric);
+    defaults.into_iter().next().map(|route| route.interface)
+}
+
+//the interface traffic to the internet leaves through, VPN or not
+pub fn internet_interface() -> Option<String> {
+    //any address out on the internet will do
+    let addr = u32::from(Ipv4Addr::new(1, 1, 1, 1));
+    let mut matching: Vec<Ipv4Route> = ipv4_routes().into_iter()
+        .filter(|route| addr & route.mask == route.destination)
+        .collect();
+    //the longest prefix wins, then the lowest metric
+    matching.sort_by_key(|route| (!route.mask, route.metric));
+    matching.into_iter().next().map(|route| route.interface)
+}
+
+struct Ipv4Route {
+    interface: String,
+    destination: u32,
+    mask: u32,
+    metric: u32,
+}
+
+//the main routing table. addresses are in network order, printed as native integers
+fn ipv4_routes() -> Vec<Ipv4Route> {
+    let mut content = String::new();
+    if let Err(e) = File::open("/proc/net/route").and_then(|mut file| file.read_to_string(&mut content)) {
+        println!("cannot read the routing table: {}", e);
+        return vec![];
+    }
+    let hex = |field: &str| u32::from_str_radix(field, 16).ok().map(u32::from_be);
+    content.lines().skip(1)
+        .filter_map(|line| {
+            //Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            Some(Ipv4Route {
+                interface: fields[0].to_string(),
+                destination: hex(fields[1])?,
+                mask: hex(fields[7])?,
+                metric: fields[6].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+//an attribute of the interface from sysfs, trimmed
+pub fn read_sys(interface: &str, attribute: &str) -> Option<String> {
+    let mut content = String::new();
+    File::open(format!("/sys/class/net/{}/{}", interface, attribute)).ok()?
+        .read_to_string(&mut content).ok()?;
+    Some(content.trim().to_string())
+}
+
+//addresses assigned to the interface, used when binding to the device is not permitted
+pub fn interface_addrs(interface: &str) -> Vec<IpAddr> {
+    let mut addrs = vec![];
+    unsafe {
+        let mut ifaddrs: *mut libc::ifaddrs = ptr::null_mut();
+        if libc::getifaddrs(&mut ifaddrs) != 0 {
+            return addrs;
+        }
+        let mut cursor = ifaddrs;
+        while !cursor.is_null() {
+            let ifaddr = &*cursor;
+            cursor = ifaddr.ifa_next;
+            if ifaddr.ifa_addr.is_null() || CStr::from_ptr(ifaddr.ifa_name).to_bytes() != interface.as_bytes() {
+                continue;
+            }
+            match (*ifaddr.ifa_addr).sa_family as i32 {
+                libc::AF_INET => {
+                    let sin = &*(ifaddr.ifa_addr as *const libc::sockaddr_in);
+                    addrs.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr))));
+                },
+                libc::AF_INET6 => {
+                    let sin6 = &*(ifaddr.ifa_addr as *const libc::sockaddr_in6);
+                    addrs.push(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)));
+                },
+                _ => {},
+            }
+        }
+        libc::freeifaddrs(ifaddrs);
+    }
+    addrs
+}
+
+//connects through the given interface instead of whatever the default route is
+pub fn connect(addr: &SocketAddr, interface: Option<&str>, handle: &Handle) -> Box<Future<Item=TcpStream, Error=io::Error>> {
+    let domain = if addr.is_ipv4() { Domain::ipv4() } else { Domain::ipv6() };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp())).and_then(|socket| {
+        if let Some(interface) = interface {
+            bind(&socket, addr, interface)?;
+        }
+        Ok(socket)
+    });
+    match socket {
+        Ok(socket) => {
+            let stream = TcpStream::connect_stream(socket.into_tcp_stream(), addr, handle);
+            reactor::timeout(stream, Duration::from_secs(CONNECT_TIMEOUT_SECS), handle)
+        },
+        Err(e) => Box::new(future::err(e)),
+    }
+}
+
+pub fn bind(socket: &Socket, addr: &SocketAddr, interface: &str) -> io::Result<()> {
+    match bind_to_device(socket.as_raw_fd(), interface) {
+        Ok(()) => return Ok(()),
+        Err(ref e) if e.raw_os_error() == Some(libc::EPERM) => {},
+        Err(e) => return Err(e),
+    }
+
+    //SO_BINDTODEVICE needs CAP_NET_RAW, the source address is the next best thing
+    let source = interface_addrs(interface).into_iter()
+        .find(|source| source.is_ipv4() == addr.is_ipv4())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable,
+                                      format!("{} has no address for {}", interface, addr)))?;
+    socket.bind(&SockAddr::from(SocketAddr::new(source, 0)))
+}
+
+fn bind_to_device(fd: RawFd, interface: &str) -> io::Result<()> {
+    let name = CString::new(interface).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let len = name.as_bytes_with_nul().len() as libc::socklen_t;
+    let ret = unsafe {
+        libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, name.as_ptr() as *const libc::c_void, len)
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    pub fn matches(&self, addr: &SocketAddr) -> bool {
+        match *self {
+            Family::Ipv4 => addr.is_ipv4(),
+            Family::Ipv6 => addr.is_ipv6(),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::ptr;
+use gdk::{self, ModifierType};
+use gdk::enums::key as gdk_key;
+use gdk_sys;
+use glib_sys;
+use servo::compositing::windowing::WindowEvent;
+use servo::msg::constellation_msg::{Key, KeyModifiers, KeyState};
+
+//translates a key event into the character it types and the servo key.
+//the character is always delivered, characters without a key of their own
+//are sent as World1 like glfw does
+pub fn translate(event: &gdk::EventKey) -> Option<(Option<char>, Key)> {
+    let base = base_keyval(event.get_hardware_keycode() as u32, event.get_group() as i32);
+    translate_keyval(event.get_keyval(), base)
+}
+
+//printable keys are told apart by the keyval the key types without modifiers, so
+//Shift+1 is Num1 whatever symbol the layout puts above the 1
+fn translate_keyval(keyval: gdk_key::Key, base: Option<gdk_key::Key>) -> Option<(Option<char>, Key)> {
+    let unicode =
+        gdk::keyval_to_unicode(keyval).and_then(|ch| {
+            if ch.is_control() {
+                None
+            } else {
+                Some(ch)
+            }
+        });
+    let key = match unicode {
+        Some(_) => base.and_then(keysym_to_key).or_else(|| keysym_to_key(keyval)),
+        None => keysym_to_key(keyval),
+    };
+    match (unicode, key) {
+        (ch, Some(key)) => Some((ch, key)),
+        (Some(ch), None) => Some((Some(ch), Key::World1)),
+        (None, None) => None,
+    }
+}
+
+//the keyval at the first level of a key in the given layout group
+fn base_keyval(keycode: u32, group: i32) -> Option<gdk_key::Key> {
+    unsafe {
+        let keymap = gdk_sys::gdk_keymap_get_default();
+        if keymap.is_null() {
+            return None;
+        }
+        let key = gdk_sys::GdkKeymapKey {
+            keycode: keycode,
+            group: group,
+            level: 0,
+        };
+        match gdk_sys::gdk_keymap_lookup_key(keymap, &key) {
+            0 => None,
+            keyval => Some(keyval),
+        }
+    }
+}
+
+//the first key of the current keymap that types the keyval, as keycode and group
+fn key_for_keyval(keyval: gdk_key::Key) -> Option<(u32, i32)> {
+    unsafe {
+        let keymap = gdk_sys::gdk_keymap_get_default();
+        if keymap.is_null() {
+            return None;
+        }
+        let mut keys: *mut gdk_sys::GdkKeymapKey = ptr::null_mut();
+        let mut count = 0;
+        if gdk_sys::gdk_keymap_get_entries_for_keyval(keymap, keyval, &mut keys, &mut count) == 0 {
+            return None;
+        }
+        let first = if count > 0 {
+            Some(((*keys).keycode, (*keys).group))
+        } else {
+            None
+        };
+        glib_sys::g_free(keys as glib_sys::gpointer);
+        first
+    }
+}
+
+//turns text committed by the input method into key presses
+pub fn text_events(text: &str) -> Vec<WindowEvent> {
+    let mut events = vec![];
+    for ch in text.chars() {
+        let key = char_to_key(ch);
+        events.push(WindowEvent::KeyEvent(Some(ch), key, KeyState::Pressed, KeyModifiers::empty()));
+        events.push(WindowEvent::KeyEvent(Some(ch), key, KeyState::Released, KeyModifiers::empty()));
+    }
+    events
+}
+
+//a Ctrl+key press as sent by the copy, cut and select all actions
+pub fn shortcut_events(ch: char, key: Key) -> Vec<WindowEvent> {
+    vec![
+        WindowEvent::KeyEvent(Some(ch), key, KeyState::Pressed, KeyModifiers::CONTROL),
+        WindowEvent::KeyEvent(Some(ch), key, KeyState::Released, KeyModifiers::CONTROL),
+    ]
+}
+
+pub fn to_modifier(mods: ModifierType) -> KeyModifiers {
+    let mut key_mods = KeyModifiers::empty();
+    if mods.contains(gdk::META_MASK) {
+        key_mods.insert(KeyModifiers::ALT);
+    }
+    if mods.contains(gdk::SUPER_MASK) {
+        key_mods.insert(KeyModifiers::SUPER);
+    }
+    if mods.contains(gdk::CONTROL_MASK) {
+        key_mods.insert(KeyModifiers::CONTROL);
+    }
+    if mods.contains(gdk::SHIFT_MASK) {
+        key_mods.insert(KeyModifiers::SHIFT);
+    }
+    key_mods
+}
+
+//text has no key events of its own, so the key is looked up in the keymap.
+//characters the layout cannot type are sent as World1
+fn char_to_key(ch: char) -> Key {
+    let keyval = gdk::unicode_to_keyval(ch as u32);
+    key_for_keyval(keyval)
+        .and_then(|(keycode, group)| base_keyval(keycode, group))
+        .and_then(keysym_to_key)
+        .or_else(|| keysym_to_key(keyval))
+        .unwrap_or(Key::World1)
+}
+
+fn keysym_to_key(keyval: gdk_key::Key) -> Option<Key> {
+    let key = match keyval {
+        gdk_key::space => Key::Space,
+        gdk_key::apostrophe => Key::Apostrophe,
+        gdk_key::comma => Key::Comma,
+        gdk_key::minus => Key::Minus,
+        gdk_key::period => Key::Period,
+        gdk_key::slash => Key::Slash,
+        gdk_key::_0 => Key::Num0,
+        gdk_key::_1 => Key::Num1,
+        gdk_key::_2 => Key::Num2,
+        gdk_key::_3 => Key::Num3,
+        gdk_key::_4 => Key::Num4,
+        gdk_key::_5 => Key::Num5,
+        gdk_key::_6 => Key::Num6,
+        gdk_key::_7 => Key::Num7,
+        gdk_key::_8 => Key::Num8,
+        gdk_key::_9 => Key::Num9,
+        gdk_key::semicolon => Key::Semicolon,
+        gdk_key::equal => Key::Equal,
+        gdk_key::A | gdk_key::a => Key::A,
+        gdk_key::B | gdk_key::b => Key::B,
+        gdk_key::C | gdk_key::c => Key::C,
+        gdk_key::D | gdk_key::d => Key::D,
+        gdk_key::E | gdk_key::e => Key::E,
+        gdk_key::F | gdk_key::f => Key::F,
+        gdk_key::G | gdk_key::g => Key::G,
+        gdk_key::H | gdk_key::h => Key::H,
+        gdk_key::I | gdk_key::i => Key::I,
+        gdk_key::J | gdk_key::j => Key::J,
+        gdk_key::K | gdk_key::k => Key::K,
+        gdk_key::L | gdk_key::l => Key::L,
+        gdk_key::M | gdk_key::m => Key::M,
+        gdk_key::N | gdk_key::n => Key::N,
+        gdk_key::O | gdk_key::o => Key::O,
+        gdk_key::P | gdk_key::p => Key::P,
+        gdk_key::Q | gdk_key::q => Key::Q,
+        gdk_key::R | gdk_key::r => Key::R,
+        gdk_key::S | gdk_key::s => Key::S,
+        gdk_key::T | gdk_key::t => Key::T,
+        gdk_key::U | gdk_key::u => Key::U,
+        gdk_key::V | gdk_key::v => Key::V,
+        gdk_key::W | gdk_key::w => Key::W,
+        gdk_key::X | gdk_key::x => Key::X,
+        gdk_key::Y | gdk_key::y => Key::Y,
+        gdk_key::Z | gdk_key::z => Key::Z,
+        gdk_key::bracketleft => Key::LeftBracket,
+        gdk_key::backslash => Key::Backslash,
+        gdk_key::bracketright => Key::RightBracket,
+        gdk_key::grave => Key::GraveAccent,
+        gdk_key::Escape => Key::Escape,
+        gdk_key::Return => Key::Enter,
+        gdk_key::Tab => Key::Tab,
+        gdk_key::BackSpace => Key::Backspace,
+        gdk_key::Insert => Key::Insert,
+        gdk_key::Delete => Key::Delete,
+        gdk_key::Right => Key::Right,
+        gdk_key::Left => Key::Left,
+        gdk_key::Down => Key::Down,
+        gdk_key::Up => Key::Up,
+        gdk_key::Page_Up => Key::PageUp,
+        gdk_key::Page_Down => Key::PageDown,
+        gdk_key::Home => Key::Home,
+        gdk_key::End => Key::End,
+        gdk_key::Caps_Lock => Key::CapsLock,
+        gdk_key::Scroll_Lock => Key::ScrollLock,
+        gdk_key::Num_Lock => Key::NumLock,
+        gdk_key::Print | gdk_key::_3270_PrintScreen => Key::PrintScreen,
+        gdk_key::Pause => Key::Pause,
+        gdk_key::F1 => Key::F1,
+        gdk_key::F2 => Key::F2,
+        gdk_key::F3 => Key::F3,
+        gdk_key::F4 => Key::F4,
+        gdk_key::F5 => Key::F5,
+        gdk_key::F6 => Key::F6,
+        gdk_key::F7 => Key::F7,
+        gdk_key::F8 => Key::F8,
+        gdk_key::F9 => Key::F9,
+        gdk_key::F10 => Key::F10,
+        gdk_key::F11 => Key::F11,
+        gdk_key::F12 => Key::F12,
+        gdk_key::F13 => Key::F13,
+        gdk_key::F14 => Key::F14,
+        gdk_key::F15 => Key::F15,
+        gdk_key::F16 => Key::F16,
+        gdk_key::F17 => Key::F17,
+        gdk_key::F18 => Key::F18,
+        gdk_key::F19 => Key::F19,
+        gdk_key::F20 => Key::F20,
+        gdk_key::F21 => Key::F21,
+        gdk_key::F22 => Key::F22,
+        gdk_key::F23 => Key::F23,
+        gdk_key::F24 => Key::F24,
+        gdk_key::F25 => Key::F25,
+        gdk_key::KP_0 => Key::Kp0,
+        gdk_key::KP_1 => Key::Kp1,
+        gdk_key::KP_2 => Key::Kp2,
+        gdk_key::KP_3 => Key::Kp3,
+        gdk_key::KP_4 => Key::Kp4,
+        gdk_key::KP_5 => Key::Kp5,
+        gdk_key::KP_6 => Key::Kp6,
+        gdk_key::KP_7 => Key::Kp7,
+        gdk_key::KP_8 => Key::Kp8,
+        gdk_key::KP_9 => Key::Kp9,
+        gdk_key::KP_Decimal => Key::KpDecimal,
+        gdk_key::KP_Divide => Key::KpDivide,
+        gdk_key::KP_Multiply => Key::KpMultiply,
+        gdk_key::KP_Subtract => Key::KpSubtract,
+        gdk_key::KP_Add => Key::KpAdd,
+        gdk_key::KP_Enter => Key::KpEnter,
+        gdk_key::KP_Equal => Key::KpEqual,
+        gdk_key::Shift_L => Key::LeftShift,
+        gdk_key::Control_L => Key::LeftControl,
+        gdk_key::Alt_L => Key::LeftAlt,
+        gdk_key::Super_L => Key::LeftSuper,
+        gdk_key::Shift_R => Key::RightShift,
+        gdk_key::Control_R => Key::RightControl,
+        gdk_key::Alt_R => Key::RightAlt,
+        gdk_key::Super_R => Key::RightSuper,
+        gdk_key::Menu => Key::Menu,
+        gdk_key::Back => Key::NavigateBackward,
+        gdk_key::Forward => Key::NavigateForward,
+        //World1 is sent for characters without a key, see char_to_key
+        _ => return None
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use gdk::enums::key as gdk_key;
+    use servo::msg::constellation_msg::Key;
+    use super::translate_keyval;
+
+    #[test]
+    fn translate_keyvals() {
+        let fixtures = [
+            (gdk_key::a, Some(gdk_key::a), Some((Some('a'), Key::A))),
+            (gdk_key::A, Some(gdk_key::a), Some((Some('A'), Key::A))),
+            (gdk_key::_1, Some(gdk_key::_1), Some((Some('1'), Key::Num1))),
+            (gdk_key::exclam, Some(gdk_key::_1), Some((Some('!'), Key::Num1))),
+            (gdk_key::at, Some(gdk_key::_2), Some((Some('@'), Key::Num2))),
+            (gdk_key::underscore, Some(gdk_key::minus), Some((Some('_'), Key::Minus))),
+            (gdk_key::question, Some(gdk_key::slash), Some((Some('?'), Key::Slash))),
+            (gdk_key::grave, Some(gdk_key::grave), Some((Some('`'), Key::GraveAccent))),
+            //German layout
+            (gdk_key::quotedbl, Some(gdk_key::_2), Some((Some('"'), Key::Num2))),
+            (gdk_key::question, Some(gdk_key::ssharp), Some((Some('?'), Key::World1))),
+            (gdk_key::y, Some(gdk_key::y), Some((Some('y'), Key::Y))),
+            (gdk_key::udiaeresis, Some(gdk_key::udiaeresis), Some((Some('\u{fc}'), Key::World1))),
+            //French layout, the digits are shifted
+            (gdk_key::_1, Some(gdk_key::ampersand), Some((Some('1'), Key::Num1))),
+            (gdk_key::eacute, Some(gdk_key::eacute), Some((Some('\u{e9}'), Key::World1))),
+            (gdk_key::Cyrillic_a, Some(gdk_key::Cyrillic_a), Some((Some('\u{430}'), Key::World1))),
+            (gdk_key::EuroSign, Some(gdk_key::e), Some((Some('\u{20ac}'), Key::E))),
+            (gdk_key::exclam, None, Some((Some('!'), Key::World1))),
+            (gdk_key::KP_1, Some(gdk_key::KP_End), Some((Some('1'), Key::Kp1))),
+            (gdk_key::Return, Some(gdk_key::Return), Some((None, Key::Enter))),
+            (gdk_key::BackSpace, Some(gdk_key::BackSpace), Some((None, Key::Backspace))),
+            (gdk_key::Left, Some(gdk_key::Left), Some((None, Key::Left))),
+            (gdk_key::Print, Some(gdk_key::Print), Some((None, Key::PrintScreen))),
+            (gdk_key::Back, None, Some((None, Key::NavigateBackward))),
+            (gdk_key::Forward, None, Some((None, Key::NavigateForward))),
+            (gdk_key::VoidSymbol, None, None),
+        ];
+
+        for &(keyval, base, expected) in fixtures.iter() {
+            assert_eq!(translate_keyval(keyval, base), expected, "keyval {:#x}", keyval);
+        }
+    }
+}
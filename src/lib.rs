@@ -0,0 +1,28 @@
+//portal detection, kept apart from the browser. servo is only built for the binary, so
+//`cargo test --no-default-features` checks detection without building it
+extern crate gtk;
+extern crate glib_sys;
+extern crate gio_sys;
+extern crate gobject_sys;
+extern crate hyper;
+extern crate socket2;
+extern crate libc;
+extern crate openssl;
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_io;
+extern crate serde_json;
+extern crate url;
+extern crate tokio_openssl;
+
+pub mod profile;
+pub mod interface;
+pub mod dns;
+pub mod proxy;
+pub mod hijack;
+pub mod reactor;
+pub mod http;
+pub mod tls;
+pub mod probe;
+pub mod vendor;
+pub mod adapter;
@@ -2,13 +2,21 @@ extern crate gtk;
 extern crate gio;
 extern crate gdk;
 extern crate gdk_sys;
+extern crate glib_sys;
 extern crate servo;
 extern crate epoxy;
 extern crate shared_library;
 extern crate glib_itc;
-extern crate hyper;
+extern crate glib;
+extern crate futures;
+extern crate tokio_core;
+extern crate cafcon;
 
 use std::env::Args;
+use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::panic::AssertUnwindSafe;
+use std::process;
 
 use gio::{
     ApplicationExt, ApplicationExtManual, SimpleActionExt, ActionMapExt,
@@ -16,15 +24,19 @@ use gio::{
 };
 
 use gtk::{
-    WidgetExt, GtkApplicationExt,
+    WidgetExt, GtkApplicationExt, DialogExt, WindowExt,
 };
-use hyper::Client;
-use hyper::status::StatusCode;
-use hyper::header::Location;
-use hyper::client::RedirectPolicy;
 
 mod window;
-use window::Context;
+mod keymap;
+mod event_pump;
+mod downloads;
+mod headless;
+use cafcon::{profile, proxy, reactor, probe, adapter};
+use window::{Context, Window};
+use profile::{Profile, PortalRule};
+use probe::{Route, Detection, Failure, HttpProber};
+use futures::Future;
 
 fn init_actions(app: &gtk::Application) {
     let quit_action = gio::SimpleAction::new("quit", None);
@@ -38,11 +50,189 @@ fn init_actions(app: &gtk::Application) {
     app.add_action(&quit_action);
 }
 
+//may be overridden in the [accels] section of the profile
+const DEFAULT_ACCELS: &[(&str, &[&str])] = &[
+    ("app.quit", &["Escape", "<Control>w"]),
+    ("win.back-history", &["<Alt>Left", "XF86Back"]),
+    ("win.forward-history", &["<Alt>Right", "XF86Forward"]),
+    ("win.reload", &["F5", "<Control>r"]),
+    ("win.home", &["<Alt>Home"]),
+    ("win.focus-location", &["<Control>l"]),
+    ("win.zoom-in", &["<Control>plus", "<Control>equal", "<Control>KP_Add"]),
+    ("win.zoom-out", &["<Control>minus", "<Control>KP_Subtract"]),
+    ("win.zoom-reset", &["<Control>0"]),
+    ("win.log-in", &["<Control>Return"]),
+    ("win.log-out", &["<Control><Shift>q"]),
+];
+
 fn init_accels(app: &gtk::Application) {
-    app.add_accelerator("Escape", "app.quit", None);
+    let profile = Profile::load();
+    let mut accels: BTreeMap<&str, Vec<&str>> = DEFAULT_ACCELS.iter()
+        .map(|&(action, accels)| (action, accels.to_vec()))
+        .collect();
+
+    for (action, custom) in profile.accels() {
+        accels.insert(action, custom);
+    }
+
+    //gtk drops the whole list of an action when one accelerator in it does not parse
+    for (action, accels) in accels {
+        let valid: Vec<&str> = accels.into_iter().filter(|accel| {
+            let (key, _) = gtk::accelerator_parse(accel);
+            if key == 0 {
+                println!("ignoring unknown accelerator {} for {}", accel, action);
+            }
+            key != 0
+        }).collect();
+        app.set_accels_for_action(action, &valid);
+    }
+}
+
+struct Options {
+    interface: Option<String>,
+    direct: bool,
+    rule: PortalRule,
+    headless: bool,
+    //the login page to log out of
+    logout: Option<String>,
+}
+
+//takes out the options gtk does not know about, the profile fills in the rest
+fn parse_options(args: Args) -> (Vec<String>, Options) {
+    let mut rest = vec![];
+    let mut interface = None;
+    let mut direct = false;
+    let mut headless = false;
+    let mut logout = None;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        if arg == "--interface" {
+            interface = args.next();
+        } else if arg.starts_with("--interface=") {
+            interface = Some(arg["--interface=".len()..].to_string());
+        } else if arg == "--direct" {
+            direct = true;
+        } else if arg == "--headless" {
+            headless = true;
+        } else if arg == "--logout" {
+            logout = args.next();
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    let profile = Profile::load();
+    (rest, Options {
+        interface: interface.or_else(|| profile.interface().map(|s| s.to_string())),
+        direct: direct || profile.direct_dns(),
+        rule: profile.portal_rule(),
+        headless: headless,
+        logout: logout,
+    })
+}
+
+fn show_error(message: &str) {
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>, gtk::DIALOG_MODAL, gtk::MessageType::Error, gtk::ButtonsType::Close, message);
+    dialog.run();
+    dialog.destroy();
+}
+
+//returns whether the user wants to open the url anyway
+fn ask_open(message: &str, url: &str) -> bool {
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>, gtk::DIALOG_MODAL, gtk::MessageType::Warning, gtk::ButtonsType::None, message);
+    dialog.add_button("Close", gtk::ResponseType::Close.into());
+    dialog.add_button(&format!("Open {}", url), gtk::ResponseType::Accept.into());
+    let response = dialog.run();
+    dialog.destroy();
+    response == gtk::ResponseType::Accept.into()
+}
+
+//probes on the reactor while a dialog tells the user detection is still going on
+fn start_detection(app: &gtk::Application, test_url: String, interface: Option<String>, direct: bool, rule: PortalRule) {
+    let checking = gtk::MessageDialog::new(
+        None::<&gtk::Window>, gtk::DialogFlags::empty(), gtk::MessageType::Info, gtk::ButtonsType::Cancel,
+        "Checking the network...");
+    //keeps the application running meanwhile
+    checking.set_application(Some(app));
+    checking.show_all();
+
+    let detection = probe::detect(&HttpProber, &test_url, interface.as_ref().map(|s| s.as_str()), direct, rule, &reactor::handle());
+    let app = app.clone();
+    let guard = {
+        let checking = checking.clone();
+        //a panicking probe would otherwise unwind into GLib and leave the dialog up
+        reactor::spawn(AssertUnwindSafe(detection).catch_unwind().then(move |result| {
+            checking.destroy();
+            match result {
+                Ok(result) => show_detection(&app, &test_url, interface.as_ref().map(|s| s.as_str()), rule, result),
+                Err(_) => show_error("Checking the network failed."),
+            }
+            Ok(())
+        }))
+    };
+
+    //dropping the guard stops the probes
+    let guard = RefCell::new(Some(guard));
+    checking.connect_response(move |checking, _| {
+        guard.borrow_mut().take();
+        checking.destroy();
+    });
+}
+
+fn show_detection(app: &gtk::Application, test_url: &str, interface: Option<&str>, rule: PortalRule,
+                  result: Result<Detection, Failure>) {
+    match result {
+        Ok(detection) => {
+            let ctx = Context::new(app, &detection.auth_url, test_url, detection.route, rule);
+            let win = ctx.borrow().window.gtk_window.clone();
+            win.show_all();
+            Window::identify_portal(&ctx.borrow().window, &detection.auth_url);
+            if let Some(diagnosis) = detection.diagnosis {
+                ctx.borrow().window.show_diagnosis(&diagnosis);
+            }
+        },
+        //as before detection could fail, an open network just ends the program
+        Err(ref failure) if failure.online => println!("{}", &failure.message),
+        Err(failure) => {
+            println!("{}", &failure.message);
+            match failure.http_fallback {
+                Some(ref url) => {
+                    if ask_open(&failure.message, url) {
+                        let ctx = Context::new(app, url, test_url, Route::system(interface), rule);
+                        let win = ctx.borrow().window.gtk_window.clone();
+                        win.show_all();
+                    }
+                },
+                None => show_error(&failure.message),
+            }
+        },
+    }
 }
 
 fn run(args: Args) {
+    let (args, options) = parse_options(args);
+    let interface = options.interface;
+    let direct = options.direct;
+    let rule = options.rule;
+
+    if let Some(auth_url) = options.logout {
+        let logged_out = headless::log_out(&auth_url, interface.as_ref().map(|s| s.as_str()));
+        process::exit(if logged_out { 0 } else { 1 });
+    }
+    //the first argument after the program is the test url, as when opened by the browser
+    if options.headless {
+        let logged_in = match args.get(1) {
+            Some(test_url) => headless::log_in(test_url, interface.as_ref().map(|s| s.as_str()), direct, rule),
+            None => {
+                println!("--headless needs the test url");
+                false
+            },
+        };
+        process::exit(if logged_in { 0 } else { 1 });
+    }
+
     match gtk::Application::new("com.github.koji-m.cafe_auth", gio::APPLICATION_HANDLES_OPEN) {
         Ok(app) => {
             {
@@ -53,8 +243,10 @@ fn run(args: Args) {
             }
 
             {
+                let interface = interface.clone();
                 app.connect_activate(move |app| {
-                    let ctx = Context::new(app, "http://www.google.com", "http://www.google.com");
+                    let route = Route::system(interface.as_ref().map(|s| s.as_str()));
+                    let ctx = Context::new(app, "http://www.google.com", "http://www.google.com", route, rule);
                     let win = ctx.borrow().window.gtk_window.clone();
                     win.show_all();
                 });
@@ -63,17 +255,12 @@ fn run(args: Args) {
             {
                 app.connect_open(move |app, urls, _| {
                     if let Some(test_url) = urls[0].get_uri() {
-                        if let Some(auth_url) = check_auth_url(&test_url) {
-                            let ctx = Context::new(app, &auth_url, &test_url);
-                            let win = ctx.borrow().window.gtk_window.clone();
-                            win.show_all();
-                        }
+                        start_detection(app, test_url, interface.clone(), direct, rule);
                     }
                 });
             }
 
 
-            let args: Vec<String> = args.collect();
             let argv: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
 
             app.run(argv.as_slice());
@@ -85,23 +272,6 @@ fn run(args: Args) {
     };
 }
 
-fn check_auth_url(test_url: &str) -> Option<String> {
-    let mut checker = Client::new();
-    checker.set_redirect_policy(RedirectPolicy::FollowNone);
-    if let Ok(res) = checker.head(test_url).send() {
-        if res.status == StatusCode::Found {
-            if let Some(url) = res.headers.get::<Location>() {
-                println!("redirect: {}", &url);
-                return Some(String::from(url.as_str()));
-            }
-            println!("no location field");
-        }
-        println!("status code {:?}", res.status);
-    }
-    println!("http head failed");
-    return None;
-}
-
 fn main() {
     run(std::env::args());
 }
@@ -0,0 +1,609 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use futures::{future, Future};
+use futures::future::Loop;
+use hyper::Url;
+use tokio_core::reactor::Handle;
+use interface::{self, Family};
+use profile::PortalRule;
+use dns;
+use hijack::{self, DnsSignal};
+use http::{self, Response};
+use reactor;
+use tls::{self, TlsOutcome};
+
+//a probe is sent at most this many times when it runs into a transient error
+const MAX_ATTEMPTS: u32 = 3;
+const BACKOFF_BASE_MILLIS: u64 = 500;
+
+//which way probes leave the machine
+#[derive(Clone, Debug, Default)]
+pub struct Route {
+    pub interface: Option<String>,
+    //used instead of the system resolver when not empty
+    pub dns_servers: Vec<IpAddr>,
+}
+
+impl Route {
+    pub fn system(interface: Option<&str>) -> Route {
+        Route {
+            interface: interface.map(|interface| interface.to_string()),
+            dns_servers: vec![],
+        }
+    }
+
+    //straight out of the captive interface with its DHCP name servers, past VPN routes,
+    //local DNS proxies and encrypted DNS
+    pub fn direct(interface: Option<&str>) -> Result<Route, String> {
+        let interface = match interface {
+            Some(interface) => interface.to_string(),
+            None => interface::physical_interface().ok_or("no interface to probe directly")?,
+        };
+        let dns_servers = dns::dhcp_servers(&interface);
+        if dns_servers.is_empty() {
+            return Err(format!("no DHCP name servers on {}", interface));
+        }
+        Ok(Route {
+            interface: Some(interface),
+            dns_servers: dns_servers,
+        })
+    }
+
+    fn interface(&self) -> Option<&str> {
+        self.interface.as_ref().map(|s| s.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Connectivity {
+    Online,
+    Portal(String),
+    NoRoute,
+}
+
+impl Connectivity {
+    fn auth_url(&self) -> Option<&str> {
+        match *self {
+            Connectivity::Portal(ref auth_url) => Some(auth_url),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Connectivity::Online => write!(f, "online"),
+            Connectivity::Portal(ref auth_url) => write!(f, "portal {}", auth_url),
+            Connectivity::NoRoute => write!(f, "no route"),
+        }
+    }
+}
+
+//portals may intercept one family and leave the other open
+#[derive(Clone, Debug)]
+pub struct FamilyResults {
+    pub ipv4: Connectivity,
+    pub ipv6: Connectivity,
+}
+
+impl FamilyResults {
+    //the login page to show under the rule, if any
+    pub fn portal(&self, rule: PortalRule) -> Option<&str> {
+        match rule {
+            PortalRule::Any => self.ipv4.auth_url().or(self.ipv6.auth_url()),
+            PortalRule::Ipv4 => self.ipv4.auth_url(),
+            PortalRule::Ipv6 => self.ipv6.auth_url(),
+            PortalRule::All => {
+                //a family without a route has nothing to say
+                let routed: Vec<&Connectivity> = vec![&self.ipv4, &self.ipv6].into_iter()
+                    .filter(|result| **result != Connectivity::NoRoute)
+                    .collect();
+                if routed.iter().all(|result| result.auth_url().is_some()) {
+                    routed.first().and_then(|&result| result.auth_url())
+                } else {
+                    None
+                }
+            },
+        }
+    }
+
+    pub fn is_online(&self, rule: PortalRule) -> bool {
+        self.portal(rule).is_none() &&
+            (self.ipv4 == Connectivity::Online || self.ipv6 == Connectivity::Online)
+    }
+}
+
+impl fmt::Display for FamilyResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IPv4 {}, IPv6 {}", self.ipv4, self.ipv6)
+    }
+}
+
+//the lowest confidence at which the login page is shown
+const PORTAL_CONFIDENCE: f32 = 0.5;
+
+//the HTTP probes and the DNS answers weighed together
+#[derive(Debug)]
+pub struct Verdict {
+    pub auth_url: Option<String>,
+    pub confidence: f32,
+    //the login page address was only guessed from hijacked DNS answers
+    pub from_dns: bool,
+    //the check URL came through untouched
+    pub online: bool,
+    pub reasons: Vec<String>,
+}
+
+impl Verdict {
+    pub fn is_portal(&self) -> bool {
+        self.auth_url.is_some() && self.confidence >= PORTAL_CONFIDENCE
+    }
+
+    fn diagnosis(&self) -> Option<String> {
+        if self.from_dns {
+            Some(format!("The login page was guessed from hijacked DNS answers (confidence {:.0}%): {}.",
+                         self.confidence * 100.0, self.reasons.join("; ")))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Detection {
+    pub auth_url: String,
+    pub route: Route,
+    pub diagnosis: Option<String>,
+}
+
+pub struct Failure {
+    pub message: String,
+    //where the network pointed over HTTPS, which may serve the login page over plain HTTP
+    pub http_fallback: Option<String>,
+    //nothing to log in to, which is no error to show
+    pub online: bool,
+}
+
+impl Failure {
+    fn online() -> Failure {
+        Failure {
+            message: "The network is online, there is no login page.".to_string(),
+            http_fallback: None,
+            online: true,
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure {
+            message: message,
+            http_fallback: None,
+            online: false,
+        }
+    }
+}
+
+//doubles with each attempt, plus up to half of that again so retries do not line up
+fn backoff(attempt: u32) -> Duration {
+    let base = BACKOFF_BASE_MILLIS << attempt;
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|now| now.subsec_nanos() as u64)
+        .unwrap_or(0);
+    Duration::from_millis(base + nanos % (base / 2 + 1))
+}
+
+//timeouts and dropped connections, as opposed to a family that has no route at all
+fn is_transient(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted |
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => true,
+        _ => false,
+    }
+}
+
+//fetches the check URL and tells what the network did with it
+pub trait Prober {
+    fn probe(&self, test_url: &str, route: &Route, family: Family, handle: &Handle)
+             -> Box<Future<Item=Connectivity, Error=()>>;
+}
+
+//the prober that goes out to the network
+pub struct HttpProber;
+
+impl Prober for HttpProber {
+    fn probe(&self, test_url: &str, route: &Route, family: Family, handle: &Handle)
+             -> Box<Future<Item=Connectivity, Error=()>> {
+        type Step = Box<Future<Item=Loop<io::Result<Response>, u32>, Error=()>>;
+        let base = match Url::parse(test_url) {
+            Ok(base) => base,
+            Err(e) => {
+                println!("invalid test url {}: {}", test_url, e);
+                return Box::new(future::ok(Connectivity::NoRoute));
+            },
+        };
+        let test_url = test_url.to_string();
+        let route = route.clone();
+        let handle = handle.clone();
+
+        //GET rather than HEAD, some portals only say so in the page
+        let result = future::loop_fn(0, move |attempt| -> Step {
+            let handle = handle.clone();
+            Box::new(http::request("GET", &test_url, &route, Some(family), &handle).then(move |result| -> Step {
+                let retry = match result {
+                    Err(ref e) => attempt + 1 < MAX_ATTEMPTS && is_transient(e),
+                    Ok(_) => false,
+                };
+                if !retry {
+                    return Box::new(future::ok(Loop::Break(result)));
+                }
+                let delay = backoff(attempt);
+                if let Err(ref e) = result {
+                    println!("{:?} probe failed: {}, retrying in {:?}", family, e, delay);
+                }
+                Box::new(reactor::sleep(delay, &handle).then(move |_| Ok::<_, ()>(Loop::Continue(attempt + 1))))
+            }))
+        });
+        Box::new(result.map(move |result| connectivity(result, &base, family)))
+    }
+}
+
+//301 and 308 are left out, a test url that only moves to https is not a portal
+fn is_portal_redirect(status: u16) -> bool {
+    match status {
+        302 | 303 | 307 => true,
+        _ => false,
+    }
+}
+
+fn connectivity(result: io::Result<Response>, base: &Url, family: Family) -> Connectivity {
+    match result {
+        Ok(ref res) if is_portal_redirect(res.status) => {
+            match res.header("Location") {
+                Some(url) => {
+                    println!("{:?} redirect: {}", family, url);
+                    Connectivity::Portal(resolve_url(base, url))
+                },
+                None => {
+                    println!("{:?} no location field", family);
+                    Connectivity::NoRoute
+                },
+            }
+        },
+        Ok(ref res) if res.is_success() => {
+            match portal_in_page(res) {
+                Some(url) => {
+                    println!("{:?} login page named in the page: {}", family, url);
+                    Connectivity::Portal(resolve_url(base, &url))
+                },
+                None => Connectivity::Online,
+            }
+        },
+        Ok(res) => {
+            println!("{:?} status code {}", family, res.status);
+            Connectivity::NoRoute
+        },
+        Err(e) => {
+            println!("{:?} http get failed: {}", family, e);
+            Connectivity::NoRoute
+        },
+    }
+}
+
+//portals that answer the check with a page of their own instead of redirecting
+fn portal_in_page(res: &Response) -> Option<String> {
+    let body = String::from_utf8_lossy(&res.body);
+    let is_captive_json = res.header("Content-Type")
+        .map_or(false, |content_type| content_type.to_lowercase().starts_with("application/captive+json"));
+    if is_captive_json {
+        return captive_api_url(&body);
+    }
+    wispr_login_url(&body).or_else(|| meta_refresh_url(&body))
+}
+
+//RFC 8908 status, e.g. {"captive": true, "user-portal-url": "https://portal.example.com/"}
+fn captive_api_url(body: &str) -> Option<String> {
+    let status: serde_json::Value = match serde_json::from_str(body) {
+        Ok(status) => status,
+        Err(e) => {
+            println!("malformed captive portal api status: {}", e);
+            return None;
+        },
+    };
+    if status["captive"].as_bool() != Some(true) {
+        return None;
+    }
+    status["user-portal-url"].as_str().map(|url| url.to_string())
+}
+
+//WISPr gateways hide an XML block with the login URL in the page
+fn wispr_login_url(body: &str) -> Option<String> {
+    if !body.contains("<WISPAccessGatewayParam") {
+        return None;
+    }
+    let start = body.find("<LoginURL>")? + "<LoginURL>".len();
+    let end = start + body[start..].find("</LoginURL>")?;
+    Some(body[start..end].trim().replace("&amp;", "&"))
+}
+
+//<meta http-equiv="refresh" content="0; url=http://portal.example.com/">
+fn meta_refresh_url(body: &str) -> Option<String> {
+    //ASCII lowercasing keeps the offsets valid in the original
+    let lower = body.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find("<meta") {
+        let start = from + pos;
+        let end = start + lower[start..].find('>')?;
+        from = end;
+        if !lower[start..end].contains("refresh") {
+            continue;
+        }
+        if let Some(url_pos) = lower[start..end].find("url=") {
+            let url = body[start + url_pos + "url=".len()..end]
+                .trim_left_matches(|ch: char| ch == '\'' || ch == ' ')
+                .split(|ch: char| ch == '"' || ch == '\'')
+                .next()
+                .unwrap_or("")
+                .trim();
+            if !url.is_empty() {
+                return Some(url.replace("&amp;", "&"));
+            }
+        }
+    }
+    None
+}
+
+//login pages are often given relative to the portal's own host
+fn resolve_url(base: &Url, url: &str) -> String {
+    base.join(url).map(|url| url.into_string()).unwrap_or_else(|_| url.to_string())
+}
+
+//both families at once
+pub fn probe_families(prober: &Prober, test_url: &str, route: &Route, handle: &Handle)
+                      -> Box<Future<Item=FamilyResults, Error=()>> {
+    let interface = route.interface().unwrap_or("default route").to_string();
+    let results = prober.probe(test_url, route, Family::Ipv4, handle)
+        .join(prober.probe(test_url, route, Family::Ipv6, handle));
+    Box::new(results.map(move |(ipv4, ipv6)| {
+        let results = FamilyResults {
+            ipv4: ipv4,
+            ipv6: ipv6,
+        };
+        println!("{} on {}", &results, interface);
+        results
+    }))
+}
+
+pub fn examine(prober: &Prober, test_url: &str, route: &Route, rule: PortalRule, handle: &Handle)
+               -> Box<Future<Item=Verdict, Error=()>> {
+    let signals = probe_families(prober, test_url, route, handle).join(hijack::detect(route, handle));
+    Box::new(signals.map(move |(results, dns)| weigh(&results, dns, rule)))
+}
+
+fn weigh(results: &FamilyResults, dns: DnsSignal, rule: PortalRule) -> Verdict {
+    let http_auth_url = results.portal(rule).map(|auth_url| auth_url.to_string());
+    let online = results.is_online(rule);
+    let http_score = if http_auth_url.is_some() {
+        0.8
+    } else if online {
+        0.0
+    } else {
+        0.3
+    };
+
+    let confidence = 1.0 - (1.0 - http_score) * (1.0 - dns.score);
+    let mut reasons = vec![results.to_string()];
+    reasons.extend(dns.reasons);
+
+    //a portal that only hijacks DNS serves its login page on the address it answers with.
+    //when the check URL came through, the hijacking is the resolver's, e.g. an ISP that
+    //answers unknown names with its search page, and never makes a portal on its own
+    let dns_gateway = if online { None } else { dns.gateway };
+    let from_dns = http_auth_url.is_none() && dns_gateway.is_some();
+    let auth_url = http_auth_url.or_else(|| dns_gateway.map(|gateway| match gateway {
+        IpAddr::V4(_) => format!("http://{}/", gateway),
+        IpAddr::V6(_) => format!("http://[{}]/", gateway),
+    }));
+
+    println!("portal confidence {:.0}%: {}", confidence * 100.0, reasons.join("; "));
+    Verdict {
+        auth_url: auth_url,
+        confidence: confidence,
+        from_dns: from_dns,
+        online: online,
+        reasons: reasons,
+    }
+}
+
+pub fn is_online(prober: &Prober, test_url: &str, route: &Route, rule: PortalRule, handle: &Handle)
+                 -> Box<Future<Item=bool, Error=()>> {
+    Box::new(probe_families(prober, test_url, route, handle).map(move |results| results.is_online(rule)))
+}
+
+//probes the given interface, or every interface that is up when none is given, all at
+//once. with direct set, probes go past VPNs and the system resolver, see Route::direct
+pub fn detect(prober: &Prober, test_url: &str, interface: Option<&str>, direct: bool, rule: PortalRule,
+              handle: &Handle) -> Box<Future<Item=Detection, Error=Failure>> {
+    if direct {
+        return detect_direct(prober, test_url, interface, rule, handle);
+    }
+    detect_on(prober, test_url, system_routes(interface), rule, handle)
+}
+
+//the first route found captive wins, in the order given
+pub fn detect_on(prober: &Prober, test_url: &str, routes: Vec<Route>, rule: PortalRule, handle: &Handle)
+                 -> Box<Future<Item=Detection, Error=Failure>> {
+    if routes.is_empty() {
+        return Box::new(future::err(Failure::from("No route to probe.".to_string())));
+    }
+    let verdicts: Vec<_> = routes.iter().map(|route| examine(prober, test_url, route, rule, handle)).collect();
+    let test_url = test_url.to_string();
+    let handle = handle.clone();
+    Box::new(future::join_all(verdicts).map_err(|_| Failure::from("Probing failed.".to_string()))
+        .and_then(move |verdicts| -> Box<Future<Item=Detection, Error=Failure>> {
+            let verdicts_online = verdicts.iter().any(|verdict| verdict.online);
+            for (route, verdict) in routes.iter().zip(verdicts) {
+                if verdict.is_portal() {
+                    return Box::new(future::ok(Detection {
+                        diagnosis: verdict.diagnosis(),
+                        auth_url: verdict.auth_url.unwrap(),
+                        route: route.clone(),
+                    }));
+                }
+            }
+
+            if verdicts_online {
+                return Box::new(future::err(Failure::online()));
+            }
+            let interceptors = interceptors();
+            let message = if interceptors.is_empty() {
+                "No login page was found.".to_string()
+            } else {
+                format!("A VPN is intercepting probes ({}). Try again with --direct.", interceptors.join(", "))
+            };
+            https_diagnosis(&test_url, &routes[0], message, &handle)
+        }))
+}
+
+fn detect_direct(prober: &Prober, test_url: &str, interface: Option<&str>, rule: PortalRule, handle: &Handle)
+                 -> Box<Future<Item=Detection, Error=Failure>> {
+    let route = match Route::direct(interface) {
+        Ok(route) => route,
+        Err(e) => return Box::new(future::err(Failure::from(e))),
+    };
+    //the system routes tell whether going direct was what made the difference
+    let system_verdicts: Vec<_> = system_routes(interface).iter()
+        .map(|route| examine(prober, test_url, route, rule, handle))
+        .collect();
+    let verdicts = examine(prober, test_url, &route, rule, handle).join(future::join_all(system_verdicts));
+    let test_url = test_url.to_string();
+    let handle = handle.clone();
+    Box::new(verdicts.map_err(|_| Failure::from("Probing failed.".to_string()))
+        .and_then(move |(verdict, system_verdicts)| -> Box<Future<Item=Detection, Error=Failure>> {
+            if verdict.online {
+                return Box::new(future::err(Failure::online()));
+            }
+            if !verdict.is_portal() {
+                let message = format!("No login page was found on {}.", route.interface().unwrap_or("any interface"));
+                return https_diagnosis(&test_url, &route, message, &handle);
+            }
+
+            let intercepted = !system_verdicts.iter().any(|verdict| verdict.is_portal());
+            let diagnosis = if intercepted {
+                let interceptors = interceptors();
+                Some(if interceptors.is_empty() {
+                    "VPN/proxy is intercepting probes, the login page was only reachable directly.".to_string()
+                } else {
+                    format!("VPN/proxy is intercepting probes ({}), the login page was only reachable directly.",
+                            interceptors.join(", "))
+                })
+            } else {
+                verdict.diagnosis()
+            };
+
+            Box::new(future::ok(Detection {
+                auth_url: verdict.auth_url.unwrap(),
+                route: route,
+                diagnosis: diagnosis,
+            }))
+        }))
+}
+
+//networks that leave plain HTTP alone may still get in the way of HTTPS. always fails,
+//with what was found over HTTPS added to the message
+fn https_diagnosis(test_url: &str, route: &Route, message: String, handle: &Handle)
+                   -> Box<Future<Item=Detection, Error=Failure>> {
+    let host = match Url::parse(test_url).ok().and_then(|url| url.host_str().map(|host| host.to_string())) {
+        Some(host) => host,
+        None => return Box::new(future::err(Failure::from(message))),
+    };
+
+    Box::new(tls::probe(&host, route, handle).then(move |outcome| {
+        let failure = match outcome {
+            Ok(TlsOutcome::Trusted) | Err(_) => Failure::from(message),
+            Ok(outcome) => {
+                println!("{:?}", &outcome);
+                Failure {
+                    message: format!("{} {}", message, outcome.explain(&host)),
+                    http_fallback: outcome.portal_host()
+                        .and_then(|host| Url::parse(&format!("http://{}/", host)).ok())
+                        .map(|url| url.into_string()),
+                    online: false,
+                }
+            },
+        };
+        Err::<Detection, _>(failure)
+    }))
+}
+
+fn system_routes(interface: Option<&str>) -> Vec<Route> {
+    if interface.is_some() {
+        return vec![Route::system(interface)];
+    }
+    let interfaces = interface::up_interfaces();
+    if interfaces.len() < 2 {
+        return vec![Route::system(None)];
+    }
+    interfaces.iter().map(|interface| Route::system(Some(interface))).collect()
+}
+
+//what may be keeping the portal's redirect and DNS answers from the probes: a VPN only
+//when traffic to the internet is routed through it. a configured proxy is not one, the
+//probes go through it like any other client
+fn interceptors() -> Vec<String> {
+    interface::internet_interface().into_iter()
+        .filter(|interface| interface::is_vpn(interface))
+        .map(|interface| format!("VPN on {}", interface))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use hijack::DnsSignal;
+    use profile::PortalRule;
+    use super::{Connectivity, FamilyResults, is_portal_redirect, weigh};
+
+    fn hijacked_dns() -> DnsSignal {
+        let gateway: IpAddr = "192.0.2.1".parse().unwrap();
+        DnsSignal {
+            score: 0.5,
+            gateway: Some(gateway),
+            reasons: vec!["nonexistent name resolved to 192.0.2.1".to_string()],
+        }
+    }
+
+    #[test]
+    fn hijacked_dns_does_not_override_online_probes() {
+        let results = FamilyResults {
+            ipv4: Connectivity::Online,
+            ipv6: Connectivity::Online,
+        };
+        let verdict = weigh(&results, hijacked_dns(), PortalRule::Any);
+        assert!(!verdict.is_portal());
+        assert_eq!(verdict.auth_url, None);
+    }
+
+    #[test]
+    fn hijacked_dns_finds_the_portal_when_probes_fail() {
+        let results = FamilyResults {
+            ipv4: Connectivity::NoRoute,
+            ipv6: Connectivity::NoRoute,
+        };
+        let verdict = weigh(&results, hijacked_dns(), PortalRule::Any);
+        assert!(verdict.is_portal());
+        assert!(verdict.from_dns);
+        assert_eq!(verdict.auth_url, Some("http://192.0.2.1/".to_string()));
+    }
+
+    #[test]
+    fn permanent_redirects_are_not_portals() {
+        assert!(is_portal_redirect(302));
+        assert!(is_portal_redirect(303));
+        assert!(is_portal_redirect(307));
+        assert!(!is_portal_redirect(301));
+        assert!(!is_portal_redirect(308));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::process::Command;
+use adapter::Credentials;
+
+//user settings kept in an ini-like file, e.g.
+//
+//  [zoom]
+//  portal.example.com = 1.25
+//
+//  [accels]
+//  win.reload = F5; <Control>r
+//
+//  [popups]
+//  policy = tab
+//  max_tabs = 4
+//
+//  [network]
+//  interface = wlan0
+//  dns = dhcp
+//  portal = any
+//
+//  [portal]
+//  portal.example.com = guest
+//
+//the username is the one for the login page's host, its password is kept in the secret
+//service instead of here, e.g.
+//
+//  secret-tool store --label="cafcon portal.example.com" application cafcon host portal.example.com
+//where new browsing contexts are opened
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PopupPolicy {
+    Tab,
+    Current,
+    Block,
+}
+
+//which address families have to be captive for the login page to be shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PortalRule {
+    Any,
+    Ipv4,
+    Ipv6,
+    All,
+}
+
+pub struct Profile {
+    path: PathBuf,
+    sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Profile {
+    pub fn load() -> Profile {
+        let path = profile_path();
+        let mut sections = BTreeMap::new();
+        let mut content = String::new();
+        if let Ok(mut file) = File::open(&path) {
+            if file.read_to_string(&mut content).is_err() {
+                println!("failed to read profile {:?}", &path);
+                content.clear();
+            }
+        }
+
+        let mut section = String::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+            if let Some(pos) = line.find('=') {
+                let key = line[..pos].trim().to_string();
+                let value = line[pos + 1..].trim().to_string();
+                sections.entry(section.clone()).or_insert_with(BTreeMap::new).insert(key, value);
+            }
+        }
+
+        //a password used to be kept in the file for every portal alike
+        if sections.get_mut("portal").and_then(|entries| entries.remove("password")).is_some() {
+            println!("the portal password in {:?} is ignored and dropped on the next save, store it for the \
+                      portal's host with secret-tool as described in profile.rs", &path);
+        }
+
+        Profile {
+            path: path,
+            sections: sections,
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        //the portal usernames and networks are kept here, so only the user may read the file
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&self.path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        for (section, entries) in &self.sections {
+            if entries.is_empty() {
+                continue;
+            }
+            writeln!(file, "[{}]", section)?;
+            for (key, value) in entries {
+                writeln!(file, "{} = {}", key, value)?;
+            }
+            writeln!(file, "")?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections.get(section)
+            .and_then(|entries| entries.get(key))
+            .map(|value| value.as_str())
+    }
+
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        self.sections.entry(section.to_string()).or_insert_with(BTreeMap::new)
+            .insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, section: &str, key: &str) {
+        if let Some(entries) = self.sections.get_mut(section) {
+            entries.remove(key);
+        }
+    }
+
+    pub fn entries(&self, section: &str) -> Vec<(&str, &str)> {
+        match self.sections.get(section) {
+            Some(entries) => entries.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect(),
+            None => vec![],
+        }
+    }
+
+    //accelerators are separated by ';', an empty value unbinds the action
+    pub fn accels(&self) -> Vec<(&str, Vec<&str>)> {
+        self.entries("accels").into_iter()
+            .map(|(action, accels)| {
+                (action, accels.split(';').map(|accel| accel.trim()).filter(|accel| !accel.is_empty()).collect())
+            })
+            .collect()
+    }
+
+    pub fn popup_policy(&self) -> PopupPolicy {
+        match self.get("popups", "policy") {
+            Some("current") => PopupPolicy::Current,
+            Some("block") => PopupPolicy::Block,
+            _ => PopupPolicy::Tab,
+        }
+    }
+
+    //including the login page
+    pub fn max_tabs(&self) -> usize {
+        self.get("popups", "max_tabs").and_then(|max| max.parse().ok()).unwrap_or(4)
+    }
+
+    //the interface to probe and log in on, overridden by --interface
+    pub fn interface(&self) -> Option<&str> {
+        self.get("network", "interface")
+    }
+
+    //probe with the interface's DHCP name servers instead of the system resolver
+    pub fn direct_dns(&self) -> bool {
+        self.get("network", "dns") == Some("dhcp")
+    }
+
+    pub fn portal_rule(&self) -> PortalRule {
+        match self.get("network", "portal") {
+            Some("ipv4") => PortalRule::Ipv4,
+            Some("ipv6") => PortalRule::Ipv6,
+            Some("all") => PortalRule::All,
+            _ => PortalRule::Any,
+        }
+    }
+
+    //for portals whose product is known, see adapter.rs. host is the login page's, the
+    //credentials are only for the portal found there
+    pub fn credentials(&self, host: &str) -> Credentials {
+        Credentials {
+            username: self.get("portal", host).unwrap_or("").to_string(),
+            password: stored_password(host).unwrap_or_default(),
+        }
+    }
+
+    pub fn zoom(&self, host: &str) -> Option<f32> {
+        self.get("zoom", host).and_then(|level| level.parse().ok())
+    }
+
+    pub fn set_zoom(&mut self, host: &str, level: f32) {
+        if level == 1.0 {
+            self.remove("zoom", host);
+        } else {
+            self.set("zoom", host, &level.to_string());
+        }
+    }
+}
+
+//the password secret-tool stored for the portal's host, none when there is none
+fn stored_password(host: &str) -> Option<String> {
+    let output = match Command::new("secret-tool").args(&["lookup", "application", "cafcon", "host", host]).output() {
+        Ok(output) => output,
+        Err(e) => {
+            println!("could not run secret-tool: {}", e);
+            return None;
+        },
+    };
+    //a lookup that finds nothing fails without saying anything
+    if !output.status.success() {
+        if !output.stderr.is_empty() {
+            println!("secret-tool failed: {}", String::from_utf8_lossy(&output.stderr));
+        }
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+fn profile_path() -> PathBuf {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::home_dir().unwrap_or_else(|| PathBuf::from(".")).join(".config"),
+    };
+    config_dir.join("cafcon").join("profile.ini")
+}
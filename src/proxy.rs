@@ -0,0 +1,114 @@
+use std::env;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::ptr;
+use futures::{future, Future};
+use futures::sync::oneshot;
+use gio_sys;
+use glib_sys;
+use gobject_sys;
+use hyper::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Proxy {
+    Direct,
+    Http(String, u16),
+}
+
+//the proxy for the url: the environment first, then GIO's resolver, which follows the
+//GNOME proxy settings including automatic configuration by WPAD and PAC files. GIO
+//runs a PAC script in its own worker and answers on the main loop
+pub fn lookup_async(url: &str) -> Box<Future<Item=Proxy, Error=io::Error>> {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return Box::new(future::ok(Proxy::Direct)),
+    };
+    if let Some(proxy) = from_env(&url) {
+        return Box::new(future::ok(proxy));
+    }
+    from_gio(&url)
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names.iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+fn from_env(url: &Url) -> Option<Proxy> {
+    let host = url.host_str().unwrap_or("");
+    if let Some(no_proxy) = env_var(&["no_proxy", "NO_PROXY"]) {
+        if no_proxy.split(',').map(|entry| entry.trim()).any(|entry| bypasses(host, entry)) {
+            return Some(Proxy::Direct);
+        }
+    }
+
+    let proxy = match url.scheme() {
+        "https" => env_var(&["https_proxy", "HTTPS_PROXY"]),
+        //curl and friends ignore the upper case one for http, as it can be set by CGI
+        _ => env_var(&["http_proxy"]),
+    };
+    proxy.or_else(|| env_var(&["all_proxy", "ALL_PROXY"])).and_then(|proxy| parse(&proxy))
+}
+
+fn bypasses(host: &str, entry: &str) -> bool {
+    let entry = entry.trim_left_matches('.');
+    !entry.is_empty() && (entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+//only http proxies are spoken, others are skipped
+fn parse(proxy: &str) -> Option<Proxy> {
+    if proxy == "direct://" {
+        return Some(Proxy::Direct);
+    }
+    let url = if proxy.contains("://") { Url::parse(proxy) } else { Url::parse(&format!("http://{}", proxy)) };
+    match url {
+        Ok(ref url) if url.scheme() == "http" => {
+            url.host_str().map(|host| Proxy::Http(host.to_string(), url.port().unwrap_or(8080)))
+        },
+        _ => {
+            println!("unsupported proxy {}", proxy);
+            None
+        },
+    }
+}
+
+fn from_gio(url: &Url) -> Box<Future<Item=Proxy, Error=io::Error>> {
+    let uri = match CString::new(url.as_str()) {
+        Ok(uri) => uri,
+        Err(_) => return Box::new(future::ok(Proxy::Direct)),
+    };
+    let (tx, rx) = oneshot::channel::<Proxy>();
+    unsafe {
+        let resolver = gio_sys::g_proxy_resolver_get_default();
+        let data = Box::into_raw(Box::new(tx)) as glib_sys::gpointer;
+        gio_sys::g_proxy_resolver_lookup_async(resolver, uri.as_ptr(), ptr::null_mut(), Some(looked_up), data);
+    }
+    Box::new(rx.map_err(|_| io::Error::new(io::ErrorKind::Other, "proxy lookup failed")))
+}
+
+unsafe extern "C" fn looked_up(source: *mut gobject_sys::GObject, result: *mut gio_sys::GAsyncResult,
+                               data: glib_sys::gpointer) {
+    let tx = Box::from_raw(data as *mut oneshot::Sender<Proxy>);
+    let mut error: *mut glib_sys::GError = ptr::null_mut();
+    let list = gio_sys::g_proxy_resolver_lookup_finish(source as *mut gio_sys::GProxyResolver, result, &mut error);
+    if !error.is_null() {
+        println!("proxy lookup failed: {}", CStr::from_ptr((*error).message).to_string_lossy());
+        glib_sys::g_error_free(error);
+        tx.send(Proxy::Direct).ok();
+        return;
+    }
+    if list.is_null() {
+        tx.send(Proxy::Direct).ok();
+        return;
+    }
+
+    let mut proxies = vec![];
+    let mut entry = list;
+    while !(*entry).is_null() {
+        proxies.push(CStr::from_ptr(*entry).to_string_lossy().into_owned());
+        entry = entry.offset(1);
+    }
+    glib_sys::g_strfreev(list);
+    tx.send(proxies.iter().filter_map(|proxy| parse(proxy)).next().unwrap_or(Proxy::Direct)).ok();
+}
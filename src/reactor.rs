@@ -0,0 +1,139 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+use futures::{future, Future};
+use futures::sync::oneshot;
+use glib_sys;
+use gtk::{self, Continue};
+use libc::c_int;
+use tokio_core::reactor::{Core, Handle, Timeout};
+
+struct Reactor {
+    core: RefCell<Core>,
+    handle: Handle,
+    //the epoll descriptor, readable whenever a socket or a spawned task is ready
+    fd: RawFd,
+    watching: Cell<bool>,
+}
+
+thread_local! {
+    static REACTOR: Reactor = {
+        let core = Core::new().expect("cannot create the reactor");
+        let handle = core.handle();
+        let fd = core.as_raw_fd();
+        Reactor {
+            core: RefCell::new(core),
+            handle: handle,
+            fd: fd,
+            watching: Cell::new(false),
+        }
+    };
+}
+
+//adds the reactor's descriptor to the GLib main context, the main loop then turns the
+//reactor when it is ready
+fn watch(reactor: &Reactor) {
+    reactor.watching.set(true);
+    unsafe {
+        glib_sys::g_unix_fd_add(reactor.fd, glib_sys::G_IO_IN, Some(dispatch), ptr::null_mut());
+    }
+}
+
+unsafe extern "C" fn dispatch(_fd: c_int, _condition: glib_sys::GIOCondition, _data: glib_sys::gpointer)
+                              -> glib_sys::gboolean {
+    REACTOR.with(|reactor| {
+        if turn(reactor) {
+            glib_sys::GTRUE
+        } else {
+            //the descriptor stays readable until the busy turn is done, which watches again
+            reactor.watching.set(false);
+            glib_sys::GFALSE
+        }
+    })
+}
+
+//false when the reactor is already turning further up the stack, as when a callback
+//opens a modal dialog and so nests the main loop
+fn turn(reactor: &Reactor) -> bool {
+    let turned = match reactor.core.try_borrow_mut() {
+        Ok(mut core) => {
+            core.turn(Some(Duration::from_millis(0)));
+            true
+        },
+        Err(_) => false,
+    };
+    if turned && !reactor.watching.get() {
+        watch(reactor);
+    }
+    turned
+}
+
+fn with_reactor<R, F: FnOnce(&Reactor) -> R>(f: F) -> R {
+    REACTOR.with(|reactor| {
+        if !reactor.watching.get() {
+            watch(reactor);
+        }
+        f(reactor)
+    })
+}
+
+pub fn handle() -> Handle {
+    with_reactor(|reactor| reactor.handle.clone())
+}
+
+//cancels the spawned future when dropped
+pub struct Guard {
+    _cancel: oneshot::Sender<()>,
+}
+
+//runs the future on the GTK thread, alongside the GLib main loop
+pub fn spawn<F>(future: F) -> Guard
+    where F: Future<Item=(), Error=()> + 'static
+{
+    let (cancel_tx, cancel_rx) = oneshot::channel::<()>();
+    let future = future.select(cancel_rx.then(|_| Ok(()))).then(|_| Ok(()));
+    with_reactor(|reactor| reactor.handle.spawn(future));
+
+    Guard {
+        _cancel: cancel_tx,
+    }
+}
+
+//tokio keeps its timers to itself rather than on the descriptor, so a timer of the GLib
+//driven reactor also gets a GLib timeout that turns the reactor once it is due
+fn wake_after(duration: Duration, handle: &Handle) {
+    let ours = REACTOR.with(|reactor| reactor.handle.id() == handle.id());
+    if !ours {
+        return;
+    }
+    let millis = duration.as_secs() as u32 * 1000 + duration.subsec_nanos() / 1_000_000 + 1;
+    gtk::timeout_add(millis, || {
+        Continue(!REACTOR.with(|reactor| turn(reactor)))
+    });
+}
+
+//fails with TimedOut when the future takes longer than the duration
+pub fn timeout<F>(future: F, duration: Duration, handle: &Handle) -> Box<Future<Item=F::Item, Error=io::Error>>
+    where F: Future<Error=io::Error> + 'static, F::Item: 'static
+{
+    let timeout = match Timeout::new(duration, handle) {
+        Ok(timeout) => timeout,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    wake_after(duration, handle);
+    let expired = timeout.and_then(|_| Err::<F::Item, _>(io::Error::new(io::ErrorKind::TimedOut, "timed out")));
+    Box::new(future.select(expired).map(|(item, _)| item).map_err(|(e, _)| e))
+}
+
+//resolves after the duration, for backing off
+pub fn sleep(duration: Duration, handle: &Handle) -> Box<Future<Item=(), Error=io::Error>> {
+    match Timeout::new(duration, handle) {
+        Ok(timeout) => {
+            wake_after(duration, handle);
+            Box::new(timeout)
+        },
+        Err(e) => Box::new(future::err(e)),
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use futures::{future, Future};
+use openssl::nid;
+use openssl::ssl::{SslConnectorBuilder, SslMethod, SSL_VERIFY_PEER};
+use openssl::x509::{X509Ref, X509NameRef};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_openssl::{SslConnectorExt, SslStream};
+use url::{Host, Url};
+use http::{self, READ_TIMEOUT_SECS};
+use interface::CONNECT_TIMEOUT_SECS;
+use probe::Route;
+use reactor;
+
+const HTTPS_PORT: u16 = 443;
+
+//what a certificate in the presented chain says about itself
+#[derive(Clone, Debug)]
+pub struct CertInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub names: Vec<String>,
+}
+
+impl CertInfo {
+    fn new(cert: &X509Ref) -> CertInfo {
+        let subject = common_name(cert.subject_name());
+        let mut names: Vec<String> = match cert.subject_alt_names() {
+            Some(alt_names) => alt_names.iter().filter_map(|name| name.dnsname().map(|name| name.to_string())).collect(),
+            None => vec![],
+        };
+        if names.is_empty() && !subject.is_empty() {
+            names.push(subject.clone());
+        }
+        CertInfo {
+            subject: subject,
+            issuer: common_name(cert.issuer_name()),
+            names: names,
+        }
+    }
+
+    fn is_for(&self, host: &str) -> bool {
+        self.names.iter().any(|name| name_matches(name, host))
+    }
+}
+
+impl fmt::Display for CertInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" issued by \"{}\"", self.subject, self.issuer)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TlsOutcome {
+    Trusted,
+    //a certificate for another host, usually the portal's own
+    Redirected(Vec<CertInfo>),
+    //a certificate for the host that is not trusted, minted by an intercepting middlebox
+    Intercepted(Vec<CertInfo>, String),
+    //no handshake at all, which says nothing about interception
+    Failed(String),
+}
+
+impl TlsOutcome {
+    //the host the network wants us to talk to instead, if it said
+    pub fn portal_host(&self) -> Option<&str> {
+        match *self {
+            //names are only offered when they are hosts, a CN may be any text
+            TlsOutcome::Redirected(ref chain) => chain.first()
+                .and_then(|cert| cert.names.iter().find(|name| !name.contains('*') && Host::parse(name).is_ok()))
+                .map(|name| name.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn explain(&self, host: &str) -> String {
+        match *self {
+            TlsOutcome::Trusted => format!("The secure connection to {} is not intercepted.", host),
+            TlsOutcome::Redirected(ref chain) => format!(
+                "The network answered the secure connection to {} with a certificate for another host, {}. \
+                 A login page is likely in the way of HTTPS.", host, describe(chain)),
+            TlsOutcome::Intercepted(ref chain, ref reason) => format!(
+                "The secure connection to {} is intercepted: the certificate {} is not trusted ({}).",
+                host, describe(chain), reason),
+            TlsOutcome::Failed(ref reason) => format!("No secure connection to {} could be made: {}.", host, reason),
+        }
+    }
+}
+
+fn describe(chain: &[CertInfo]) -> String {
+    chain.first().map_or("(none)".to_string(), |cert| cert.to_string())
+}
+
+fn common_name(name: &X509NameRef) -> String {
+    name.entries_by_nid(nid::COMMONNAME).next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map_or(String::new(), |cn| cn.to_string())
+}
+
+fn name_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let host = host.to_lowercase();
+    if pattern.starts_with("*.") {
+        //a wildcard covers a single label
+        match host.find('.') {
+            Some(pos) => host[pos + 1..] == pattern[2..],
+            None => false,
+        }
+    } else {
+        pattern == host
+    }
+}
+
+//shakes hands without failing on an untrusted chain, so the presented chain can be
+//looked at either way. the connector only asks for verification and leaves the
+//callback of the context in place, which lets every certificate through while
+//verify_result() still tells what was wrong
+pub fn probe(host: &str, route: &Route, handle: &Handle) -> Box<Future<Item=TlsOutcome, Error=()>> {
+    let connector = SslConnectorBuilder::new(SslMethod::tls()).map(|mut builder| {
+        builder.builder_mut().set_verify_callback(SSL_VERIFY_PEER, |_, _| true);
+        builder.build()
+    });
+    let connector = match connector {
+        Ok(connector) => connector,
+        Err(e) => return Box::new(future::ok(TlsOutcome::Failed(e.to_string()))),
+    };
+
+    let url = match Url::parse(&format!("https://{}:{}/", host, HTTPS_PORT)) {
+        Ok(url) => url,
+        Err(e) => return Box::new(future::ok(TlsOutcome::Failed(e.to_string()))),
+    };
+    let host = host.to_string();
+    let handshake = {
+        let host = host.clone();
+        //through the proxy like the probes, a tunnel is as good a way to see the chain
+        http::connect_to(&url, route, None, handle).and_then(move |(stream, _)| {
+            connector.connect_async(&host, stream).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        })
+    };
+    let handshake = reactor::timeout(handshake, Duration::from_secs(CONNECT_TIMEOUT_SECS + READ_TIMEOUT_SECS), handle);
+    Box::new(handshake.then(move |result| {
+        match result {
+            Ok(stream) => Ok::<_, ()>(outcome(&host, &stream)),
+            Err(e) => Ok(TlsOutcome::Failed(e.to_string())),
+        }
+    }))
+}
+
+fn outcome(host: &str, stream: &SslStream<TcpStream>) -> TlsOutcome {
+    let ssl = stream.get_ref().ssl();
+    let chain: Vec<CertInfo> = match ssl.peer_cert_chain() {
+        Some(chain) => chain.iter().map(CertInfo::new).collect(),
+        None => vec![],
+    };
+    for cert in &chain {
+        println!("{}: presented {} for {:?}", host, cert, cert.names);
+    }
+
+    let for_host = chain.first().map(|cert| cert.is_for(host));
+    match for_host {
+        None => TlsOutcome::Failed("no certificate was presented".to_string()),
+        Some(false) => TlsOutcome::Redirected(chain),
+        Some(true) => match ssl.verify_result() {
+            None => TlsOutcome::Trusted,
+            Some(error) => {
+                let reason = error.error_string().to_string();
+                TlsOutcome::Intercepted(chain, reason)
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CertInfo, TlsOutcome};
+
+    fn redirected(names: &[&str]) -> TlsOutcome {
+        TlsOutcome::Redirected(vec![CertInfo {
+            subject: names[0].to_string(),
+            issuer: "Portal CA".to_string(),
+            names: names.iter().map(|name| name.to_string()).collect(),
+        }])
+    }
+
+    #[test]
+    fn portal_host_skips_names_that_are_not_hosts() {
+        assert_eq!(redirected(&["Portal Gateway"]).portal_host(), None);
+        assert_eq!(redirected(&["*.portal.example.com"]).portal_host(), None);
+        assert_eq!(redirected(&["Portal Gateway", "login.example.com"]).portal_host(), Some("login.example.com"));
+        assert_eq!(redirected(&["192.0.2.1"]).portal_host(), Some("192.0.2.1"));
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use hyper::Url;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Vendor {
+    CoovaChilli,
+    CiscoWlc,
+    CiscoIse,
+    ArubaClearPass,
+    Unifi,
+    Meraki,
+    Mikrotik,
+    PfSense,
+    OpenNds,
+    Fortinet,
+}
+
+impl fmt::Display for Vendor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Vendor::CoovaChilli => "CoovaChilli",
+            Vendor::CiscoWlc => "Cisco WLC",
+            Vendor::CiscoIse => "Cisco ISE",
+            Vendor::ArubaClearPass => "Aruba ClearPass",
+            Vendor::Unifi => "UniFi",
+            Vendor::Meraki => "Meraki",
+            Vendor::Mikrotik => "Mikrotik Hotspot",
+            Vendor::PfSense => "pfSense",
+            Vendor::OpenNds => "OpenNDS",
+            Vendor::Fortinet => "Fortinet",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//what gives a product away. hosts and page markers are matched as substrings, paths as
+//prefixes
+struct Signature {
+    vendor: Vendor,
+    hosts: &'static [&'static str],
+    ports: &'static [u16],
+    paths: &'static [&'static str],
+    params: &'static [&'static str],
+    markers: &'static [&'static str],
+}
+
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        vendor: Vendor::CoovaChilli,
+        hosts: &[],
+        ports: &[3990, 4990],
+        paths: &["/prelogin", "/logon", "/hotspotlogin"],
+        params: &["uamip", "uamport", "challenge", "nasid"],
+        markers: &["ChilliController", "chilli.js", "coova"],
+    },
+    Signature {
+        vendor: Vendor::CiscoWlc,
+        hosts: &["1.1.1.1", "virtual.wlc"],
+        ports: &[],
+        paths: &["/login.html", "/fs/customwebauth/"],
+        params: &["switch_url", "ap_mac", "client_mac", "wlan"],
+        markers: &["buttonClicked", "switch_url", "err_flag"],
+    },
+    Signature {
+        vendor: Vendor::CiscoIse,
+        hosts: &[],
+        ports: &[8443],
+        paths: &["/portal/gateway", "/portal/PortalSetup.action"],
+        params: &["sessionId"],
+        markers: &["cisco-ise", "PortalSetup.action", "token="],
+    },
+    Signature {
+        vendor: Vendor::ArubaClearPass,
+        hosts: &["securelogin.arubanetworks.com", "securelogin.hpe.com", "clearpass"],
+        ports: &[],
+        paths: &["/cgi-bin/login", "/guest/"],
+        params: &["cmd", "apname", "essid", "switchip"],
+        markers: &["ClearPass", "arubanetworks", "Aruba"],
+    },
+    Signature {
+        vendor: Vendor::Unifi,
+        hosts: &[],
+        ports: &[8880, 8843],
+        paths: &["/guest/s/"],
+        params: &["ap", "ssid"],
+        markers: &["UniFi", "unifi", "/guest/s/"],
+    },
+    Signature {
+        vendor: Vendor::Meraki,
+        hosts: &["network-auth.com"],
+        ports: &[],
+        paths: &["/splash/"],
+        params: &["base_grant_url", "continue_url", "node_mac", "client_mac", "user_continue_url"],
+        markers: &["meraki", "network-auth.com"],
+    },
+    Signature {
+        vendor: Vendor::Mikrotik,
+        hosts: &[],
+        ports: &[],
+        //its /login and /status are what every portal calls its pages
+        paths: &[],
+        params: &["dst", "popup"],
+        markers: &["mikrotik", "hexMD5(", "name=\"sendin\"", "RouterOS"],
+    },
+    Signature {
+        vendor: Vendor::PfSense,
+        hosts: &[],
+        ports: &[8002, 8003],
+        paths: &["/index.php"],
+        params: &["zone", "redirurl"],
+        markers: &["pfSense", "auth_user", "auth_voucher"],
+    },
+    Signature {
+        vendor: Vendor::OpenNds,
+        hosts: &[],
+        ports: &[2050],
+        paths: &["/opennds_preauth/", "/opennds_auth/", "/nodogsplash_auth/", "/splash.html"],
+        params: &["tok", "fas", "gatewayname"],
+        markers: &["openNDS", "opennds", "nodogsplash"],
+    },
+    Signature {
+        vendor: Vendor::Fortinet,
+        hosts: &[],
+        ports: &[1000, 1003],
+        paths: &["/fgtauth"],
+        params: &["magic"],
+        markers: &["4Tredir", "name=\"magic\"", "FortiGate", "fortinet"],
+    },
+];
+
+//weaker signs alone, like a common query parameter, are not enough. parameters that any
+//portal may use, like id or action, are left out of the signatures
+const MIN_SCORE: u32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct Fingerprint {
+    pub vendor: Vendor,
+    pub score: u32,
+    pub evidence: Vec<String>,
+    //the page itself showed a marker of the product, not only its address
+    pub page_confirmed: bool,
+}
+
+//identifies the product from the login page address and, when fetched, the page itself
+pub fn fingerprint(auth_url: &str, page: Option<&str>) -> Option<Fingerprint> {
+    let url = Url::parse(auth_url).ok()?;
+    let host = url.host_str().unwrap_or("").to_lowercase();
+    let params: Vec<String> = url.query_pairs().map(|(key, _)| key.into_owned()).collect();
+
+    let mut best: Option<Fingerprint> = None;
+    for signature in SIGNATURES {
+        let mut score = 0;
+        let mut evidence = vec![];
+        let mut page_confirmed = false;
+        for pattern in signature.hosts.iter().filter(|pattern| host.contains(*pattern)) {
+            score += 3;
+            evidence.push(format!("host {}", pattern));
+        }
+        if let Some(port) = url.port().and_then(|port| signature.ports.iter().find(|&&known| known == port)) {
+            score += 1;
+            evidence.push(format!("port {}", port));
+        }
+        for path in signature.paths.iter().filter(|path| url.path().starts_with(*path)) {
+            score += 2;
+            evidence.push(format!("path {}", path));
+        }
+        for param in signature.params.iter().filter(|param| params.iter().any(|key| key.as_str() == **param)) {
+            score += 1;
+            evidence.push(format!("parameter {}", param));
+        }
+        if let Some(page) = page {
+            for marker in signature.markers.iter().filter(|marker| page.contains(*marker)) {
+                score += 2;
+                page_confirmed = true;
+                evidence.push(format!("page contains {}", marker));
+            }
+        }
+
+        if score >= MIN_SCORE && best.as_ref().map_or(true, |best| score > best.score) {
+            best = Some(Fingerprint {
+                vendor: signature.vendor,
+                score: score,
+                evidence: evidence,
+                page_confirmed: page_confirmed,
+            });
+        }
+    }
+    best
+}
@@ -1,17 +1,17 @@
 use std::path::Path;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ptr;
 use std::env;
-use std::sync::{Arc, Mutex, mpsc};
-use std::thread;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
 use servo;
 use servo::gl;
 use servo::BrowserId;
 use servo::compositing::windowing::{WindowEvent, WindowMethods, MouseWindowEvent};
 use servo::compositing::compositor_thread::EventLoopWaker;
+use servo::servo_config::opts;
 use servo::servo_config::resource_files::set_resources_path;
-//use servo::servo_config::opts;
 use servo::euclid::{
     Point2D, ScaleFactor, Size2D, TypedPoint2D, TypedRect, TypedSize2D, TypedVector2D
 };
@@ -22,31 +22,49 @@ use servo::servo_geometry::DeviceIndependentPixel;
 use servo::servo_url::ServoUrl;
 use servo::style_traits::DevicePixel;
 use servo::style_traits::cursor::Cursor;
-use servo::script_traits::{MouseButton, TouchEventType};
+use servo::script_traits::{MouseButton, TouchEventType, TouchId};
+use servo::webrender_api::ScrollLocation;
 use servo::msg::constellation_msg::{
     Key, KeyModifiers, KeyState, TopLevelBrowsingContextId, TraversalDirection,
 };
 use epoxy;
 use shared_library::dynamic_library::DynamicLibrary;
 use glib_itc::{Receiver, Sender, channel};
+use futures::Future;
+use glib::{ToVariant, VariantTy};
+use glib::translate::ToGlibPtr;
 use gio::{
     self, ActionMapExt, SimpleActionExt, ActionExt,
 };
 use gtk::{
     self, WidgetExt, WindowExt, GLAreaExt, Inhibit, Continue, ButtonExt,
+    EventControllerExt, GestureExt, IMContextExt, LabelExt, PopoverExt, EntryExt,
+    ContainerExt, BoxExt, InfoBarExt, DialogExt,
 };
 use gdk::{
     self, CONTROL_MASK, ScrollDirection, BUTTON_PRESS_MASK, POINTER_MOTION_MASK,
-    SCROLL_MASK, BUTTON_RELEASE_MASK, ScreenExt, WindowExt as _GdkWinExt
+    SCROLL_MASK, SMOOTH_SCROLL_MASK, TOUCH_MASK, BUTTON_RELEASE_MASK, ScreenExt, WindowExt as _GdkWinExt
 };
 use gdk::enums::key as gdk_key;
-use gdk_sys::{GDK_BUTTON_MIDDLE, GDK_BUTTON_PRIMARY, GDK_BUTTON_SECONDARY};
-use hyper::Client;
-use hyper::status::StatusCode;
-use hyper::client::RedirectPolicy;
+use gdk_sys::{self, GDK_BUTTON_MIDDLE, GDK_BUTTON_PRIMARY, GDK_BUTTON_SECONDARY};
+use profile::{Profile, PopupPolicy, PortalRule};
+use keymap;
+use event_pump::{self, EventQueue};
+use downloads::Downloads;
+use probe::{Route, Prober, HttpProber};
+use adapter::{self, Adapter};
+use reactor;
+use proxy::{self, Proxy};
 
 
 const LINE_HEIGHT: f32 = 38.0;
+const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.3;
+const MAX_ZOOM: f32 = 5.0;
+const PROFILE_SAVE_DELAY_MILLIS: u32 = 1000;
+//thumb buttons found on most mice
+const BUTTON_BACK: u32 = 8;
+const BUTTON_FORWARD: u32 = 9;
 
 struct Waker {tx: Arc<Mutex<Sender>>}
 
@@ -60,6 +78,12 @@ impl EventLoopWaker for Waker {
     }
 }
 
+pub struct Tab {
+    pub id: BrowserId,
+    pub container: gtk::Box,
+    pub button: gtk::Button,
+}
+
 pub struct Context {
     pub window: Rc<Window>,
     pub wake_rx: Receiver,
@@ -73,27 +97,55 @@ pub struct Window {
     pub waker: Box<EventLoopWaker>,
     pub forward_action: Rc<RefCell<gio::SimpleAction>>,
     pub back_action: Rc<RefCell<gio::SimpleAction>>,
+    pub history_action: Rc<RefCell<gio::SimpleAction>>,
+    pub current_url: RefCell<Option<ServoUrl>>,
+    pub history: RefCell<Vec<LoadData>>,
+    pub histories: RefCell<HashMap<BrowserId, (Vec<LoadData>, usize)>>,
+    pub browser: Cell<Option<BrowserId>>,
+    pub main_browser: Cell<Option<BrowserId>>,
+    pub tabs: RefCell<Vec<Tab>>,
+    pub tab_bar: gtk::Box,
+    pub titles: RefCell<HashMap<String, String>>,
+    pub history_gestures: RefCell<Vec<gtk::GestureLongPress>>,
+    pub zoom: Cell<f32>,
+    pub zoom_host: RefCell<Option<String>>,
+    pub zoom_button: gtk::Button,
+    pub zoom_gesture: gtk::GestureZoom,
+    pub profile: Rc<RefCell<Profile>>,
+    //a save of the profile is waiting for the zoom to settle
+    pub profile_save_pending: Rc<Cell<bool>>,
+    pub location_entry: gtk::Entry,
+    pub diagnosis_bar: gtk::InfoBar,
+    pub diagnosis_label: gtk::Label,
+    pub downloads: Rc<Downloads>,
+    pub hovered_link: RefCell<Option<String>>,
+    //urls pages asked to open in a new browsing context, opened once servo is free
+    pub popups: RefCell<Vec<ServoUrl>>,
+    pub context_link: RefCell<Option<String>>,
+    pub im_context: gtk::IMMulticontext,
+    pub preedit_label: gtk::Label,
     pub pointer: Rc<RefCell<(f64, f64)>>,
-    pub chk_req_tx: mpsc::Sender<bool>,
-    pub chg_req_rx: Receiver,
-    pub event_queue: RefCell<Vec<WindowEvent>>,
+    pub check_box: gtk::Box,
+    pub close_button: gtk::Button,
+    pub finish_icon: gtk::Image,
+    pub test_url: String,
+    pub route: Route,
+    pub rule: PortalRule,
+    pub prober: Box<Prober>,
+    pub recheck: RefCell<Option<reactor::Guard>>,
+    pub portal: RefCell<Option<Rc<Adapter>>>,
+    //the login page's host, whose credentials are the ones sent
+    pub portal_host: RefCell<String>,
+    pub portal_task: RefCell<Option<reactor::Guard>>,
+    pub event_queue: RefCell<EventQueue>,
 }
 
 impl Context {
-    pub fn new(app: &gtk::Application, auth_url: &str, test_url: &str) -> Rc<RefCell<Context>> {
+    pub fn new(app: &gtk::Application, auth_url: &str, test_url: &str, route: Route, rule: PortalRule) -> Rc<RefCell<Context>> {
         let builder = gtk::Builder::new_from_file(Path::new("./ui/window.ui"));
         let win: gtk::ApplicationWindow = builder.get_object("window").unwrap();
         win.set_application(Some(app));
 
-        let (mut chg_req_tx, mut chg_req_rx) = channel();
-        let finish_icon: gtk::Image = builder.get_object("finish-image").unwrap();
-        let close_button: gtk::Button = builder.get_object("close-button").unwrap();
-        chg_req_rx.connect_recv(move || {
-            close_button.set_image(&finish_icon);
-            close_button.set_label("Finish");
-            Continue(true)
-        });
-
         epoxy::load_with(|s| {
             unsafe {
                 match DynamicLibrary::open(None).unwrap().symbol(s) {
@@ -108,12 +160,47 @@ impl Context {
         };
 
         let gl_area: gtk::GLArea = builder.get_object("gl-area").unwrap();
-        gl_area.add_events((BUTTON_PRESS_MASK | BUTTON_RELEASE_MASK | POINTER_MOTION_MASK | SCROLL_MASK).bits() as i32);
+        gl_area.add_events((BUTTON_PRESS_MASK | BUTTON_RELEASE_MASK | POINTER_MOTION_MASK | SCROLL_MASK | SMOOTH_SCROLL_MASK | TOUCH_MASK).bits() as i32);
+
+        let zoom_button: gtk::Button = builder.get_object("zoom-button").unwrap();
+        let zoom_gesture = gtk::GestureZoom::new(&gl_area);
+
+        let im_context = gtk::IMMulticontext::new();
+        let preedit_label: gtk::Label = builder.get_object("preedit-label").unwrap();
+        {
+            let im_context = im_context.clone();
+            gl_area.connect_realize(move |gl_area| {
+                im_context.set_client_window(gl_area.get_window().as_ref());
+            });
+        }
+        {
+            let im_context = im_context.clone();
+            gl_area.connect_focus_in_event(move |_, _| {
+                im_context.focus_in();
+                Inhibit(false)
+            });
+        }
+        {
+            let im_context = im_context.clone();
+            gl_area.connect_focus_out_event(move |_, _| {
+                im_context.focus_out();
+                Inhibit(false)
+            });
+        }
+        //Servo has no composition events, so the preedit text is shown over the page
+        {
+            let preedit_label = preedit_label.clone();
+            im_context.connect_preedit_changed(move |im_context| {
+                let (text, _, _) = im_context.get_preedit_string();
+                preedit_label.set_text(&text);
+                preedit_label.set_visible(!text.is_empty());
+            });
+        }
 
         let (tx, rx) = channel();
-        let (chk_req_tx, chk_req_rx) = mpsc::channel();
         let dummy_back_action = Rc::new(RefCell::new(gio::SimpleAction::new("dummy_back", None)));
         let dummy_forward_action = Rc::new(RefCell::new(gio::SimpleAction::new("dummy_forward", None)));
+        let dummy_history_action = Rc::new(RefCell::new(gio::SimpleAction::new("dummy_history", None)));
 
         let window = Rc::new(Window {
             gl_area: gl_area.clone(),
@@ -123,9 +210,43 @@ impl Context {
             pointer: Rc::new(RefCell::new((0.0, 0.0))),
             forward_action: dummy_forward_action.clone(),
             back_action: dummy_back_action.clone(),
-            chk_req_tx: chk_req_tx,
-            chg_req_rx: chg_req_rx,
-            event_queue: RefCell::new(vec![]),
+            history_action: dummy_history_action.clone(),
+            current_url: RefCell::new(None),
+            history: RefCell::new(vec![]),
+            histories: RefCell::new(HashMap::new()),
+            browser: Cell::new(None),
+            main_browser: Cell::new(None),
+            tabs: RefCell::new(vec![]),
+            tab_bar: builder.get_object("tab-bar").unwrap(),
+            titles: RefCell::new(HashMap::new()),
+            history_gestures: RefCell::new(vec![]),
+            zoom: Cell::new(1.0),
+            zoom_host: RefCell::new(None),
+            zoom_button: zoom_button,
+            zoom_gesture: zoom_gesture,
+            profile: Rc::new(RefCell::new(Profile::load())),
+            profile_save_pending: Rc::new(Cell::new(false)),
+            location_entry: builder.get_object("location-entry").unwrap(),
+            diagnosis_bar: builder.get_object("diagnosis-bar").unwrap(),
+            diagnosis_label: builder.get_object("diagnosis-label").unwrap(),
+            downloads: Downloads::new(&builder),
+            hovered_link: RefCell::new(None),
+            popups: RefCell::new(vec![]),
+            context_link: RefCell::new(None),
+            im_context: im_context,
+            preedit_label: preedit_label,
+            check_box: builder.get_object("check-box").unwrap(),
+            close_button: builder.get_object("close-button").unwrap(),
+            finish_icon: builder.get_object("finish-image").unwrap(),
+            test_url: test_url.to_string(),
+            route: route,
+            rule: rule,
+            prober: Box::new(HttpProber),
+            recheck: RefCell::new(None),
+            portal: RefCell::new(None),
+            portal_host: RefCell::new(String::new()),
+            portal_task: RefCell::new(None),
+            event_queue: RefCell::new(EventQueue::new()),
         });
 
         let context = Rc::new(RefCell::new(Context {
@@ -134,6 +255,15 @@ impl Context {
             servo: None,
         }));
 
+        window.diagnosis_bar.connect_response(|bar, _| {
+            bar.set_visible(false);
+        });
+
+        let back_button: gtk::Button = builder.get_object("back-button").unwrap();
+        let forward_button: gtk::Button = builder.get_object("forward-button").unwrap();
+        Window::connect_history_menu(&window, &back_button, true);
+        Window::connect_history_menu(&window, &forward_button, false);
+
         {
             let context = context.clone();
             let auth_url = auth_url.to_string();
@@ -142,20 +272,20 @@ impl Context {
             });
         }
 
-
-        let mut checker = Client::new();
-        checker.set_redirect_policy(RedirectPolicy::FollowNone);
-        let test_url = test_url.to_string();
-        thread::spawn(move || {
-            while let Ok(true) = chk_req_rx.recv() {
-                println!("DEBUG: communication ack");
-                if let Ok(res) = checker.head(&test_url).send() {
-                    if res.status == StatusCode::Ok {
-                        chg_req_tx.send();
+        //stops a re-check or a portal request still in flight
+        {
+            let window = window.clone();
+            window.gtk_window.clone().connect_destroy(move |_| {
+                window.recheck.borrow_mut().take();
+                window.portal_task.borrow_mut().take();
+                //a zoom changed just before closing is not lost
+                if window.profile_save_pending.get() {
+                    if let Err(e) = window.profile.borrow().save() {
+                        println!("failed to save profile: {}", e);
                     }
                 }
-            }
-        });
+            });
+        }
 
         context
     }
@@ -163,7 +293,266 @@ impl Context {
 
 impl Window {
     pub fn maybe_change_close_button(&self) {
-        self.chk_req_tx.send(true).unwrap();
+        self.check_box.set_visible(true);
+        let check_box = self.check_box.clone();
+        let close_button = self.close_button.clone();
+        let finish_icon = self.finish_icon.clone();
+        //servo's network stack cannot be bound, so the page itself follows the routing table
+        let online = adapter::is_logged_in(self.portal.borrow().clone(), &*self.prober, &self.test_url, &self.route,
+                                           self.rule, &reactor::handle());
+        //replacing the guard drops a check that is still running
+        *self.recheck.borrow_mut() = Some(reactor::spawn(online.map(move |online| {
+            check_box.set_visible(false);
+            if online {
+                close_button.set_image(&finish_icon);
+                close_button.set_label("Finish");
+            }
+        })));
+    }
+
+    //explains what detection ran into, the page is still shown below
+    pub fn show_diagnosis(&self, text: &str) {
+        self.diagnosis_label.set_text(text);
+        self.diagnosis_bar.set_visible(true);
+    }
+
+    //for events raised while servo is busy calling back into the window
+    pub fn queue_event(&self, event: WindowEvent) {
+        self.event_queue.borrow_mut().push(event);
+        self.waker.wake();
+    }
+
+    //tells what product serves the login page, for logging in and out without it
+    pub fn identify_portal(window: &Rc<Window>, auth_url: &str) {
+        let identified = {
+            let window = window.clone();
+            adapter::identify(auth_url, &window.route, &reactor::handle()).map(move |portal| {
+                *window.portal.borrow_mut() = portal.adapter;
+                *window.portal_host.borrow_mut() = portal.host;
+            })
+        };
+        *window.portal_task.borrow_mut() = Some(reactor::spawn(identified));
+    }
+
+    //with the credentials from the profile, or none for portals that only want their terms accepted
+    pub fn log_in_portal(window: &Rc<Window>) {
+        let adapter = match window.portal.borrow().clone() {
+            Some(adapter) => adapter,
+            None => {
+                window.show_diagnosis("The portal's product is not known, log in on the login page.");
+                return;
+            },
+        };
+        let host = window.portal_host.borrow().clone();
+        let credentials = window.profile.borrow().credentials(&host);
+        let logged_in = {
+            let window = window.clone();
+            adapter::log_in(&*adapter, &credentials, &host, &window.route, &reactor::handle()).then(move |result| {
+                match result {
+                    Ok(()) => window.maybe_change_close_button(),
+                    Err(e) => window.show_diagnosis(&format!("Logging in failed: {}.", e)),
+                }
+                Ok(())
+            })
+        };
+        *window.portal_task.borrow_mut() = Some(reactor::spawn(logged_in));
+    }
+
+    pub fn log_out_portal(window: &Rc<Window>) {
+        let adapter = match window.portal.borrow().clone() {
+            Some(adapter) => adapter,
+            None => {
+                window.show_diagnosis("The portal's product is not known, log out on the login page.");
+                return;
+            },
+        };
+        let logged_out = {
+            let window = window.clone();
+            adapter::log_out(&*adapter, &window.route, &reactor::handle()).then(move |result| {
+                match result {
+                    Ok(()) => window.maybe_change_close_button(),
+                    Err(e) => window.show_diagnosis(&format!("Logging out failed: {}.", e)),
+                }
+                Ok(())
+            })
+        };
+        *window.portal_task.borrow_mut() = Some(reactor::spawn(logged_out));
+    }
+
+    //pops up the history list on right-click or long-press of a navigation button
+    fn connect_history_menu(window: &Rc<Window>, button: &gtk::Button, backward: bool) {
+        {
+            let window = window.clone();
+            button.connect_button_press_event(move |button, event| {
+                if event.get_button() as i32 == GDK_BUTTON_SECONDARY {
+                    window.show_history_menu(button, backward);
+                    return Inhibit(true);
+                }
+                Inhibit(false)
+            });
+        }
+
+        let gesture = gtk::GestureLongPress::new(button);
+        gesture.set_propagation_phase(gtk::PropagationPhase::Capture);
+        {
+            let window = window.clone();
+            let button = button.clone();
+            gesture.connect_pressed(move |gesture, _, _| {
+                gesture.set_state(gtk::EventSequenceState::Claimed);
+                window.show_history_menu(&button, backward);
+            });
+        }
+        window.history_gestures.borrow_mut().push(gesture);
+    }
+
+    fn show_history_menu(&self, button: &gtk::Button, backward: bool) {
+        let history = self.history.borrow();
+        let current = self.current_history_index();
+        let indices: Vec<usize> = if backward {
+            (0..current).rev().collect()
+        } else {
+            (current + 1..history.len()).collect()
+        };
+        if indices.is_empty() {
+            return;
+        }
+
+        let titles = self.titles.borrow();
+        let menu = gio::Menu::new();
+        for index in indices {
+            let url = history[index].url.as_str();
+            let label = match titles.get(url) {
+                Some(title) if !title.is_empty() => format!("{} - {}", title, url),
+                _ => url.to_string(),
+            };
+            menu.append(Some(label.as_str()), Some(format!("win.go-history({})", index).as_str()));
+        }
+
+        let popover = gtk::Popover::new_from_model(Some(button), &menu);
+        popover.show();
+    }
+
+    //returns the event that brings servo's page zoom to `level`
+    pub fn zoom_event(&self, level: f32) -> WindowEvent {
+        let level = level.max(MIN_ZOOM).min(MAX_ZOOM);
+        let magnification = level / self.zoom.get();
+        self.zoom.set(level);
+
+        self.zoom_button.set_label(&format!("{}%", (level * 100.0).round()));
+        self.zoom_button.set_visible(level != 1.0);
+
+        if let Some(ref host) = *self.zoom_host.borrow() {
+            self.profile.borrow_mut().set_zoom(host, level);
+            self.save_profile_later();
+        }
+
+        if level == 1.0 {
+            WindowEvent::ResetZoom
+        } else {
+            WindowEvent::Zoom(magnification)
+        }
+    }
+
+    //scrolling and pinching change the zoom many times a second, the profile is written
+    //once they have settled
+    fn save_profile_later(&self) {
+        if self.profile_save_pending.get() {
+            return;
+        }
+        self.profile_save_pending.set(true);
+        let profile = self.profile.clone();
+        let pending = self.profile_save_pending.clone();
+        gtk::timeout_add(PROFILE_SAVE_DELAY_MILLIS, move || {
+            pending.set(false);
+            if let Err(e) = profile.borrow().save() {
+                println!("failed to save profile: {}", e);
+            }
+            Continue(false)
+        });
+    }
+
+    fn show_context_menu(&self, x: f64, y: f64) {
+        let link = self.hovered_link.borrow().clone();
+
+        let menu = gio::Menu::new();
+        let navigation = gio::Menu::new();
+        navigation.append(Some("Back"), Some("win.back-history"));
+        navigation.append(Some("Forward"), Some("win.forward-history"));
+        navigation.append(Some("Reload"), Some("win.reload"));
+        menu.append_section(None, &navigation);
+
+        let edit = gio::Menu::new();
+        edit.append(Some("Copy"), Some("win.copy"));
+        edit.append(Some("Paste"), Some("win.paste"));
+        edit.append(Some("Select All"), Some("win.select-all"));
+        menu.append_section(None, &edit);
+
+        if link.is_some() {
+            let link_section = gio::Menu::new();
+            link_section.append(Some("Copy Link Address"), Some("win.copy-link"));
+            link_section.append(Some("Open Link in New Tab"), Some("win.open-link-tab"));
+            link_section.append(Some("Open Link in Browser"), Some("win.open-link"));
+            menu.append_section(None, &link_section);
+        }
+
+        let zoom = gio::Menu::new();
+        zoom.append(Some("Zoom In"), Some("win.zoom-in"));
+        zoom.append(Some("Zoom Out"), Some("win.zoom-out"));
+        zoom.append(Some("Reset Zoom"), Some("win.zoom-reset"));
+        menu.append_section(None, &zoom);
+
+        *self.context_link.borrow_mut() = link;
+
+        let popover = gtk::Popover::new_from_model(Some(&self.gl_area), &menu);
+        popover.set_pointing_to(&gdk::Rectangle { x: x as i32, y: y as i32, width: 1, height: 1 });
+        popover.show();
+    }
+
+    fn show_history(&self, entries: Vec<LoadData>, current: usize) {
+        *self.current_url.borrow_mut() = entries.get(current).map(|entry| entry.url.clone());
+        if let Some(ref url) = *self.current_url.borrow() {
+            if !self.location_entry.has_focus() {
+                self.location_entry.set_text(url.as_str());
+            }
+        }
+        self.history_action.borrow().set_state(&(current as i32).to_variant());
+        self.back_action.borrow().set_enabled(!entries.is_empty() && current > 0);
+        self.forward_action.borrow().set_enabled(!entries.is_empty() && current < entries.len() - 1);
+        *self.history.borrow_mut() = entries;
+
+        //restore the zoom level remembered for the new host
+        let host = self.current_url.borrow().as_ref()
+            .and_then(|url| url.host_str().map(|host| host.to_string()));
+        if host != *self.zoom_host.borrow() {
+            let level = host.as_ref()
+                .and_then(|host| self.profile.borrow().zoom(host))
+                .unwrap_or(1.0);
+            *self.zoom_host.borrow_mut() = None;
+            if level != self.zoom.get() {
+                let event = self.zoom_event(level);
+                self.queue_event(event);
+            }
+            *self.zoom_host.borrow_mut() = host;
+        }
+    }
+
+    fn add_tab(&self, id: BrowserId, label: &str) -> Tab {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        let button = gtk::Button::new_with_label(label);
+        container.pack_start(&button, false, false, 0);
+        self.tab_bar.pack_start(&container, false, false, 0);
+        container.show_all();
+        Tab {
+            id: id,
+            container: container,
+            button: button,
+        }
+    }
+
+    fn current_history_index(&self) -> usize {
+        self.history_action.borrow().get_state()
+            .and_then(|state| state.get::<i32>())
+            .unwrap_or(0) as usize
     }
 }
 
@@ -227,15 +616,39 @@ impl WindowMethods for Window {
         Size2D::new(geometry.width as u32, geometry.height as u32)
     }
 
-    fn set_page_title(&self, _id: BrowserId, title: Option<String>) {
+    fn set_page_title(&self, id: BrowserId, title: Option<String>) {
+        if let Some(ref title) = title {
+            for tab in self.tabs.borrow().iter().filter(|tab| tab.id == id && Some(id) != self.main_browser.get()) {
+                tab.button.set_label(title);
+            }
+        }
+        if Some(id) != self.browser.get() {
+            return;
+        }
+        if let Some(ref url) = *self.current_url.borrow() {
+            self.titles.borrow_mut().insert(url.as_str().to_string(), title.clone().unwrap_or_default());
+        }
         self.gtk_window.set_title(match title {
             Some(ref title) => title,
             None => "",
         });
     }
 
-    fn allow_navigation(&self, _id: BrowserId, _url: ServoUrl, chan: ipc::IpcSender<bool>) {
-        chan.send(true).ok();
+    fn allow_navigation(&self, _id: BrowserId, url: ServoUrl, chan: ipc::IpcSender<bool>) {
+        //window.open and target=_blank, as reported by userscripts/20-popup.js
+        if url.scheme() == POPUP_SCHEME {
+            chan.send(false).ok();
+            let target = url.as_url().query_pairs()
+                .find(|&(ref key, _)| key == "url")
+                .and_then(|(_, target)| ServoUrl::parse(&target).ok());
+            if let Some(target) = target {
+                self.popups.borrow_mut().push(target);
+                self.waker.wake();
+            }
+            return;
+        }
+        //servo has no download path, onboarding files are saved by us instead
+        Downloads::check(&self.downloads, url, &self.route, chan);
     }
 
     fn set_inner_size(&self, _id: BrowserId, _size: Size2D<u32>) {
@@ -247,14 +660,25 @@ impl WindowMethods for Window {
     fn set_fullscreen_state(&self, _id: BrowserId, _state: bool) {
     }
 
-    fn status(&self, _id: BrowserId, _status: Option<String>) {
+    fn status(&self, id: BrowserId, status: Option<String>) {
+        //servo reports the link under the pointer as the status
+        if Some(id) == self.browser.get() {
+            *self.hovered_link.borrow_mut() = status;
+        }
     }
 
-    fn load_start(&self, _id: BrowserId) {
+    //tabs in the background load on their own, only the shown one drives the toolbar
+    fn load_start(&self, id: BrowserId) {
+        if Some(id) != self.browser.get() {
+            return;
+        }
         println!("load_start");
     }
 
-    fn load_end(&self, _id: BrowserId) {
+    fn load_end(&self, id: BrowserId) {
+        if Some(id) != self.browser.get() {
+            return;
+        }
         self.maybe_change_close_button();
         println!("load_end");
     }
@@ -266,11 +690,12 @@ impl WindowMethods for Window {
         println!("head_parsed");
     }
 
-    fn history_changed(&self, _id: BrowserId, entries: Vec<LoadData>, current: usize) {
+    fn history_changed(&self, id: BrowserId, entries: Vec<LoadData>, current: usize) {
         println!("history_changed");
-        //TODO: should bind LoadData with back/forward action
-        self.back_action.borrow().set_enabled(!entries.is_empty() && current > 0);
-        self.forward_action.borrow().set_enabled(!entries.is_empty() && current < entries.len() - 1);
+        self.histories.borrow_mut().insert(id, (entries.clone(), current));
+        if Some(id) == self.browser.get() {
+            self.show_history(entries, current);
+        }
     }
 
     fn set_cursor(&self, cursor: Cursor) {
@@ -320,30 +745,94 @@ impl WindowMethods for Window {
     fn set_favicon(&self, _id: BrowserId, _url: ServoUrl) {
     }
 
-    fn handle_key(&self, _id: Option<BrowserId>, ch: Option<char>, key: Key, mods: KeyModifiers) {
+    fn handle_key(&self, _id: Option<BrowserId>, _ch: Option<char>, key: Key, mods: KeyModifiers) {
         println!("handle_key");
-        match (key, ch, mods) {
-            (Key::Down, None, KeyModifiers::NONE) => {
-                let delta = servo::webrender_api::ScrollLocation::Delta(TypedVector2D::new(0.0, -LINE_HEIGHT * 2.0));
-                let (x, y) = *self.pointer.borrow();
-                let origin = TypedPoint2D::new(x as i32, y as i32);
-                self.event_queue.borrow_mut().push(WindowEvent::Scroll(delta, origin, TouchEventType::Down));
+        let page = self.size().height - LINE_HEIGHT;
+        let location = match (key, mods) {
+            (Key::Down, KeyModifiers::NONE) => ScrollLocation::Delta(TypedVector2D::new(0.0, -LINE_HEIGHT * 2.0)),
+            (Key::Up, KeyModifiers::NONE) => ScrollLocation::Delta(TypedVector2D::new(0.0, LINE_HEIGHT * 2.0)),
+            (Key::Right, KeyModifiers::NONE) => ScrollLocation::Delta(TypedVector2D::new(-LINE_HEIGHT * 2.0, 0.0)),
+            (Key::Left, KeyModifiers::NONE) => ScrollLocation::Delta(TypedVector2D::new(LINE_HEIGHT * 2.0, 0.0)),
+            (Key::PageDown, KeyModifiers::NONE) |
+            (Key::Space, KeyModifiers::NONE) => ScrollLocation::Delta(TypedVector2D::new(0.0, -page)),
+            (Key::PageUp, KeyModifiers::NONE) |
+            (Key::Space, KeyModifiers::SHIFT) => ScrollLocation::Delta(TypedVector2D::new(0.0, page)),
+            (Key::Home, KeyModifiers::NONE) => ScrollLocation::Start,
+            (Key::End, KeyModifiers::NONE) => ScrollLocation::End,
+            //Backspace only gets here when no form field took it. servo is busy calling us,
+            //so the traversal is queued rather than sent through the history actions
+            (Key::Backspace, KeyModifiers::NONE) |
+            (Key::NavigateBackward, _) => {
+                if let (Some(id), true) = (self.browser.get(), self.back_action.borrow().get_enabled()) {
+                    self.queue_event(WindowEvent::Navigation(id, TraversalDirection::Back(1)));
+                }
+                return;
             },
-            (Key::Up, None, KeyModifiers::NONE) => {
-                let delta = servo::webrender_api::ScrollLocation::Delta(TypedVector2D::new(0.0, LINE_HEIGHT * 2.0));
-                let (x, y) = *self.pointer.borrow();
-                let origin = TypedPoint2D::new(x as i32, y as i32);
-                self.event_queue.borrow_mut().push(WindowEvent::Scroll(delta, origin, TouchEventType::Up));
+            (Key::NavigateForward, _) => {
+                if let (Some(id), true) = (self.browser.get(), self.forward_action.borrow().get_enabled()) {
+                    self.queue_event(WindowEvent::Navigation(id, TraversalDirection::Forward(1)));
+                }
+                return;
             },
-            _ => {
-            }
-        }
+            _ => return,
+        };
+        let (x, y) = *self.pointer.borrow();
+        let origin = TypedPoint2D::new(x as i32, y as i32);
+        self.queue_event(WindowEvent::Scroll(location, origin, TouchEventType::Move));
     }
 }
 
+//the scheme userscripts/20-popup.js navigates to for a new browsing context
+const POPUP_SCHEME: &str = "cafcon-popup";
+
+//scripts servo runs in every document, for what the window cannot do from outside the
+//page. the options are read when servo starts, so they are set before the first window
+fn init_userscripts() {
+    static INIT: Once = ONCE_INIT;
+    INIT.call_once(|| {
+        let path = env::current_dir().unwrap().join("userscripts");
+        let mut options = opts::default_opts();
+        options.userscripts = Some(path.to_str().unwrap().to_string());
+        opts::set_defaults(options);
+    });
+}
+
 fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
+    let url = match ServoUrl::parse(url) {
+        Ok(url) => url,
+        Err(e) => {
+            println!("cannot open {}: {}", url, e);
+            let gtk_window = context.borrow().window.gtk_window.clone();
+            let dialog = gtk::MessageDialog::new(
+                Some(&gtk_window), gtk::DIALOG_MODAL, gtk::MessageType::Error, gtk::ButtonsType::Close,
+                &format!("The login page {} is not a valid address.", url));
+            dialog.run();
+            dialog.destroy();
+            gtk_window.destroy();
+            return;
+        },
+    };
+
     context.borrow().window.gl_area.make_current();
 
+    //servo's network stack of this version has no proxy setting at all, so pages load
+    //directly while probes, logins and downloads go through the proxy. giving servo the
+    //proxy is out of scope here, the user is told instead of finding out by a stuck page
+    {
+        let window = context.borrow().window.clone();
+        let url = url.to_string();
+        reactor::handle().spawn(proxy::lookup_async(&url).then(move |result| {
+            if let Ok(Proxy::Http(host, port)) = result {
+                println!("servo cannot use proxy {}:{}, loading {} directly", host, port, url);
+                window.show_diagnosis(&format!(
+                    "This network has the proxy {}:{}, which the page view cannot use. Pages are loaded \
+                     without it, checks and logins go through it.", host, port));
+            }
+            Ok(())
+        }));
+    }
+
+    init_userscripts();
     let servo = Rc::new(RefCell::new(servo::Servo::new(context.borrow().window.clone())));
 
     //connect events to gl_area
@@ -351,11 +840,13 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
         let servo = servo.clone();
         let ctx = context.clone();
         context.borrow_mut().wake_rx.connect_recv(move || {
-            servo.borrow_mut().handle_events(vec![]);
-            let ctx = ctx.borrow();
-            let event_queue = &mut *ctx.window.event_queue.borrow_mut();
-            if let Some(ev) = event_queue.pop() {
-                servo.borrow_mut().handle_events(vec![ev]);
+            let window = ctx.borrow().window.clone();
+            if event_pump::pump(&window.event_queue, &mut *servo.borrow_mut()) {
+                window.waker.wake();
+            }
+            let popups: Vec<ServoUrl> = window.popups.borrow_mut().drain(..).collect();
+            for url in popups {
+                open_browser(&window, &servo, url, Opener::Page);
             }
             Continue(true)
         });
@@ -364,12 +855,45 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
 
     {
         let servo = servo.clone();
+        context.borrow().window.im_context.connect_commit(move |_, text| {
+            servo.borrow_mut().handle_events(keymap::text_events(text));
+        });
+    }
+
+    {
+        let ctx = context.clone();
+        context.borrow().window.im_context.connect_preedit_start(move |im_context| {
+            let ctx = ctx.borrow();
+            let (x, y) = *ctx.window.pointer.borrow();
+            im_context.set_cursor_location(&gdk::Rectangle {
+                x: x as i32, y: y as i32, width: 1, height: LINE_HEIGHT as i32,
+            });
+        });
+    }
+
+    //keys consumed by the input method, whose release must not reach servo either.
+    //kept by keycode, Shift may change the keyval between press and release
+    let im_keys = Rc::new(RefCell::new(HashSet::new()));
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        let im_keys = im_keys.clone();
         context.borrow().window.gl_area.connect_key_press_event(move |_, event| {
             println!("key pressed");
-            let (ch, key) = to_key(event.get_keyval());
-            println!("key: {:?}, {:?}", ch, key);
-            if let Some(key) = key {
-                let modifier = to_modifier(event.get_state());
+            let keyval = event.get_keyval();
+            if event.get_state().contains(CONTROL_MASK) && (keyval == gdk_key::v || keyval == gdk_key::V) {
+                paste(&ctx.borrow().window, &gdk::SELECTION_CLIPBOARD);
+                return Inhibit(true);
+            }
+            if ctx.borrow().window.im_context.filter_keypress(event) {
+                im_keys.borrow_mut().insert(event.get_hardware_keycode());
+                return Inhibit(true);
+            }
+            let translated = keymap::translate(event);
+            println!("key: {:?}", translated);
+            if let Some((ch, key)) = translated {
+                let modifier = keymap::to_modifier(event.get_state());
                 servo.borrow_mut().handle_events(
                     vec![WindowEvent::KeyEvent(ch, key, KeyState::Pressed, modifier)]);
             }
@@ -379,12 +903,17 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
 
     {
         let servo = servo.clone();
+        let ctx = context.clone();
         context.borrow().window.gl_area.connect_key_release_event(move |_, event| {
             println!("key released");
-            let (ch, key) = to_key(event.get_keyval());
-            println!("key: {:?}, {:?}", ch, key);
-            if let Some(key) = key {
-                let modifier = to_modifier(event.get_state());
+            if ctx.borrow().window.im_context.filter_keypress(event) ||
+               im_keys.borrow_mut().remove(&event.get_hardware_keycode()) {
+                return Inhibit(true);
+            }
+            let translated = keymap::translate(event);
+            println!("key: {:?}", translated);
+            if let Some((ch, key)) = translated {
+                let modifier = keymap::to_modifier(event.get_state());
                 servo.borrow_mut().handle_events(
                     vec![WindowEvent::KeyEvent(ch, key, KeyState::Released, modifier)]);
             }
@@ -394,20 +923,53 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
 
     {
         let servo = servo.clone();
+        let ctx = context.clone();
         context.borrow().window.gl_area.connect_button_press_event(move |_, event| {
             let (x, y) = event.get_position();
-            let mouse_ev = MouseWindowEvent::MouseDown(to_mouse_button(event.get_button()),
-                                                       TypedPoint2D::new(x as f32, y as f32));
-            servo.borrow_mut().handle_events(vec![WindowEvent::MouseWindowEventClass(mouse_ev)]);
+            match event.get_event_type() {
+                //servo detects double clicks itself, GDK sends these in addition to the presses
+                gdk::EventType::DoubleButtonPress => return Inhibit(false),
+                gdk::EventType::TripleButtonPress => {
+                    servo.borrow_mut().handle_events(keymap::shortcut_events('a', Key::A));
+                    return Inhibit(false);
+                },
+                _ => {},
+            }
+            match event.get_button() {
+                BUTTON_BACK => {
+                    ctx.borrow().window.back_action.borrow().activate(None);
+                    return Inhibit(true);
+                },
+                BUTTON_FORWARD => {
+                    ctx.borrow().window.forward_action.borrow().activate(None);
+                    return Inhibit(true);
+                },
+                button if button as i32 == GDK_BUTTON_SECONDARY => {
+                    ctx.borrow().window.show_context_menu(x, y);
+                    return Inhibit(true);
+                },
+                _ => {},
+            }
+            if let Some(button) = to_mouse_button(event.get_button()) {
+                let mouse_ev = MouseWindowEvent::MouseDown(button, TypedPoint2D::new(x as f32, y as f32));
+                servo.borrow_mut().handle_events(vec![WindowEvent::MouseWindowEventClass(mouse_ev)]);
+            }
             Inhibit(false)
         });
     }
 
     {
         let servo = servo.clone();
+        let ctx = context.clone();
         context.borrow().window.gl_area.connect_button_release_event(move |_, event| {
+            if event.get_button() as i32 == GDK_BUTTON_SECONDARY {
+                return Inhibit(true);
+            }
             let (x, y) = event.get_position();
-            let button = to_mouse_button(event.get_button());
+            let button = match to_mouse_button(event.get_button()) {
+                Some(button) => button,
+                None => return Inhibit(false),
+            };
             let mouseup_ev = WindowEvent::MouseWindowEventClass(
                 MouseWindowEvent::MouseUp(button,
                                           TypedPoint2D::new(x as f32, y as f32)));
@@ -415,6 +977,13 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
                 MouseWindowEvent::Click(button,
                                         TypedPoint2D::new(x as f32, y as f32)));
             servo.borrow_mut().handle_events(vec![mouseup_ev, click_ev]);
+
+            //middle click pastes the primary selection of other applications into the
+            //focused field. servo does not report page selections, so they are not offered
+            //as the primary selection in turn
+            if button == MouseButton::Middle {
+                paste(&ctx.borrow().window, &gdk::SELECTION_PRIMARY);
+            }
             Inhibit(false)
         });
     }
@@ -432,6 +1001,43 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
         });
     }
 
+    //the compositor's touch handler turns these into clicks, pans and pinch zoom
+    {
+        let servo = servo.clone();
+        let touch_ids = RefCell::new(HashMap::new());
+        context.borrow().window.gl_area.connect_touch_event(move |_, event| {
+            let phase = match event.get_event_type() {
+                gdk::EventType::TouchBegin => TouchEventType::Down,
+                gdk::EventType::TouchUpdate => TouchEventType::Move,
+                gdk::EventType::TouchEnd => TouchEventType::Up,
+                gdk::EventType::TouchCancel => TouchEventType::Cancel,
+                _ => return Inhibit(false),
+            };
+            let (sequence, x, y) = touch_details(event);
+
+            let mut touch_ids = touch_ids.borrow_mut();
+            let id = match phase {
+                TouchEventType::Down => {
+                    let id = (0..).find(|id| !touch_ids.values().any(|used| used == id)).unwrap();
+                    touch_ids.insert(sequence, id);
+                    id
+                },
+                TouchEventType::Move => match touch_ids.get(&sequence) {
+                    Some(id) => *id,
+                    None => return Inhibit(true),
+                },
+                _ => match touch_ids.remove(&sequence) {
+                    Some(id) => id,
+                    None => return Inhibit(true),
+                },
+            };
+
+            let point = TypedPoint2D::new(x as f32, y as f32);
+            servo.borrow_mut().handle_events(vec![WindowEvent::Touch(phase, TouchId(id), point)]);
+            Inhibit(true)
+        });
+    }
+
     {
         let servo = servo.clone();
         context.borrow().window.gl_area.connect_resize(move |_, _, _| {
@@ -443,239 +1049,477 @@ fn init_servo(context: Rc<RefCell<Context>>, url: &str) {
     {
         let servo = servo.clone();
         let ctx= context.clone();
+        //true while a touchpad scroll sequence is in progress
+        let scrolling = Cell::new(false);
         context.borrow().window.gl_area.connect_scroll_event(move |_, event| {
             if !event.get_state().contains(CONTROL_MASK) {
-                let ev_type = match event.get_direction() {
-                    ScrollDirection::Down => TouchEventType::Down,
-                    ScrollDirection::Up => TouchEventType::Up,
-                    ScrollDirection::Left => TouchEventType::Cancel, //TODO
-                    ScrollDirection::Right => TouchEventType::Cancel, //TODO
-                    _ => TouchEventType::Cancel,
-                };
-
-                let dx: f32 = 0.0;
-                let dy: f32 = match ev_type {
-                    TouchEventType::Down => -LINE_HEIGHT,
-                    TouchEventType::Up => LINE_HEIGHT,
-                    _ => 0.0,
+                //smooth deltas are in wheel clicks, positive towards the bottom right
+                let (dx, dy, phase) = match event.get_direction() {
+                    ScrollDirection::Down => (0.0, -LINE_HEIGHT, TouchEventType::Move),
+                    ScrollDirection::Up => (0.0, LINE_HEIGHT, TouchEventType::Move),
+                    ScrollDirection::Right => (-LINE_HEIGHT, 0.0, TouchEventType::Move),
+                    ScrollDirection::Left => (LINE_HEIGHT, 0.0, TouchEventType::Move),
+                    ScrollDirection::Smooth => {
+                        let (dx, dy) = event.get_delta();
+                        let phase = if dx == 0.0 && dy == 0.0 {
+                            //the touchpad reports a zero delta when the fingers are lifted
+                            scrolling.set(false);
+                            TouchEventType::Up
+                        } else if scrolling.get() {
+                            TouchEventType::Move
+                        } else {
+                            scrolling.set(true);
+                            TouchEventType::Down
+                        };
+                        (-dx as f32 * LINE_HEIGHT, -dy as f32 * LINE_HEIGHT, phase)
+                    },
+                    _ => return Inhibit(false),
                 };
 
-                let delta = servo::webrender_api::ScrollLocation::Delta(TypedVector2D::new(dx, dy));
+                let delta = ScrollLocation::Delta(TypedVector2D::new(dx, dy));
                 let ctx = ctx.borrow();
                 let (x, y) = *ctx.window.pointer.borrow();
                 let origin = TypedPoint2D::new(x as i32, y as i32);
-                servo.borrow_mut().handle_events(vec![WindowEvent::Scroll(delta, origin, ev_type)]);
+                servo.borrow_mut().handle_events(vec![WindowEvent::Scroll(delta, origin, phase)]);
+            } else {
+                let ctx = ctx.borrow();
+                let level = ctx.window.zoom.get();
+                let event = match event.get_direction() {
+                    ScrollDirection::Up => ctx.window.zoom_event(level * ZOOM_STEP),
+                    ScrollDirection::Down => ctx.window.zoom_event(level / ZOOM_STEP),
+                    ScrollDirection::Smooth => {
+                        let (_, dy) = event.get_delta();
+                        ctx.window.zoom_event(level * ZOOM_STEP.powf(-dy as f32))
+                    },
+                    _ => return Inhibit(false),
+                };
+                servo.borrow_mut().handle_events(vec![event]);
             }
             Inhibit(false)
         });
     }
 
+    {
+        let zoom_gesture = context.borrow().window.zoom_gesture.clone();
+        let start_level = Rc::new(Cell::new(1.0));
+        {
+            let ctx = context.clone();
+            let start_level = start_level.clone();
+            zoom_gesture.connect_begin(move |_, _| {
+                start_level.set(ctx.borrow().window.zoom.get());
+            });
+        }
+        {
+            let servo = servo.clone();
+            let ctx = context.clone();
+            zoom_gesture.connect_scale_changed(move |_, scale| {
+                let event = ctx.borrow().window.zoom_event(start_level.get() * scale as f32);
+                servo.borrow_mut().handle_events(vec![event]);
+            });
+        }
+    }
+
     let path = env::current_dir().unwrap().join("resources");
     let path = path.to_str().unwrap().to_string();
     set_resources_path(Some(path));
 
-    let url = ServoUrl::parse(url).unwrap();
+    let home_url = url.clone();
     let (sender, receiver) = ipc::channel().unwrap();
     servo.borrow_mut().handle_events(vec![WindowEvent::NewBrowser(url, sender)]);
     let browser_id = receiver.recv().unwrap();
-    servo.borrow_mut().handle_events(vec![WindowEvent::SelectBrowser(browser_id)]);
+    {
+        let window = context.borrow().window.clone();
+        window.main_browser.set(Some(browser_id));
+        let tab = window.add_tab(browser_id, "Login page");
+        let servo = servo.clone();
+        let win = window.clone();
+        tab.button.connect_clicked(move |_| {
+            select_browser(&win, &servo, browser_id);
+        });
+        window.tabs.borrow_mut().push(tab);
+        select_browser(&window, &servo, browser_id);
+    }
 
-    //TODO: should be stateful by new_stateful
+    //state is the index of the current entry, parameter is the index to jump to
+    let history_action = gio::SimpleAction::new_stateful(
+        "go-history", Some(VariantTy::new("i").unwrap()), &0i32.to_variant());
     let back_action = gio::SimpleAction::new("back-history", None);
     let forward_action = gio::SimpleAction::new("forward-history", None);
 
     {
         let servo = servo.clone();
+        let ctx = context.clone();
+        history_action.connect_activate(move |action, param| {
+            let current = action.get_state().and_then(|state| state.get::<i32>());
+            let target = param.as_ref().and_then(|param| param.get::<i32>());
+            if let (Some(current), Some(target)) = (current, target) {
+                let direction = if target < current {
+                    TraversalDirection::Back((current - target) as usize)
+                } else if target > current {
+                    TraversalDirection::Forward((target - current) as usize)
+                } else {
+                    return;
+                };
+                if let Some(browser_id) = ctx.borrow().window.browser.get() {
+                    let event = WindowEvent::Navigation(browser_id, direction);
+                    servo.borrow_mut().handle_events(vec![event]);
+                }
+            }
+        });
+    }
+
+    {
+        let history_action = history_action.clone();
         back_action.connect_activate(move |_, _| {
             println!("back action");
-            //TODO: should load page using LoadData from action state
-            let event = WindowEvent::Navigation(browser_id, TraversalDirection::Back(1));
+            if let Some(current) = history_action.get_state().and_then(|state| state.get::<i32>()) {
+                history_action.activate(Some(&(current - 1).to_variant()));
+            }
+        });
+    }
+
+    {
+        let history_action = history_action.clone();
+        forward_action.connect_activate(move |_, _| {
+            if let Some(current) = history_action.get_state().and_then(|state| state.get::<i32>()) {
+                history_action.activate(Some(&(current + 1).to_variant()));
+            }
+        });
+    }
+
+    //no stop action, servo has no event to abort a load and Escape keeps quitting
+    let reload_action = gio::SimpleAction::new("reload", None);
+    let home_action = gio::SimpleAction::new("home", None);
+    let log_in_action = gio::SimpleAction::new("log-in", None);
+    let log_out_action = gio::SimpleAction::new("log-out", None);
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        reload_action.connect_activate(move |_, _| {
+            if let Some(browser_id) = ctx.borrow().window.browser.get() {
+                let event = WindowEvent::Reload(browser_id);
+                servo.borrow_mut().handle_events(vec![event]);
+            }
+        });
+    }
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        home_action.connect_activate(move |_, _| {
+            if let Some(browser_id) = ctx.borrow().window.browser.get() {
+                let event = WindowEvent::LoadUrl(browser_id, home_url.clone());
+                servo.borrow_mut().handle_events(vec![event]);
+            }
+        });
+    }
+
+    {
+        let ctx = context.clone();
+        log_in_action.connect_activate(move |_, _| {
+            let window = ctx.borrow().window.clone();
+            Window::log_in_portal(&window);
+        });
+    }
+
+    {
+        let ctx = context.clone();
+        log_out_action.connect_activate(move |_, _| {
+            let window = ctx.borrow().window.clone();
+            Window::log_out_portal(&window);
+        });
+    }
+
+    let zoom_in_action = gio::SimpleAction::new("zoom-in", None);
+    let zoom_out_action = gio::SimpleAction::new("zoom-out", None);
+    let zoom_reset_action = gio::SimpleAction::new("zoom-reset", None);
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        zoom_in_action.connect_activate(move |_, _| {
+            let ctx = ctx.borrow();
+            let event = ctx.window.zoom_event(ctx.window.zoom.get() * ZOOM_STEP);
             servo.borrow_mut().handle_events(vec![event]);
         });
     }
 
     {
         let servo = servo.clone();
-        forward_action.connect_activate(move |_, _| {
-            //TODO: should load page using LoadData from action state
-            let event = WindowEvent::Navigation(browser_id, TraversalDirection::Forward(1));
+        let ctx = context.clone();
+        zoom_out_action.connect_activate(move |_, _| {
+            let ctx = ctx.borrow();
+            let event = ctx.window.zoom_event(ctx.window.zoom.get() / ZOOM_STEP);
+            servo.borrow_mut().handle_events(vec![event]);
+        });
+    }
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        zoom_reset_action.connect_activate(move |_, _| {
+            let event = ctx.borrow().window.zoom_event(1.0);
             servo.borrow_mut().handle_events(vec![event]);
         });
     }
 
+    //copy and cut go through servo's own clipboard, paste reads the GTK clipboard
+    let copy_action = gio::SimpleAction::new("copy", None);
+    let cut_action = gio::SimpleAction::new("cut", None);
+    let paste_action = gio::SimpleAction::new("paste", None);
+
+    {
+        let servo = servo.clone();
+        copy_action.connect_activate(move |_, _| {
+            servo.borrow_mut().handle_events(keymap::shortcut_events('c', Key::C));
+        });
+    }
+
+    {
+        let servo = servo.clone();
+        cut_action.connect_activate(move |_, _| {
+            servo.borrow_mut().handle_events(keymap::shortcut_events('x', Key::X));
+        });
+    }
+
+    {
+        let ctx = context.clone();
+        paste_action.connect_activate(move |_, _| {
+            paste(&ctx.borrow().window, &gdk::SELECTION_CLIPBOARD);
+        });
+    }
+
+    let open_link_tab_action = gio::SimpleAction::new("open-link-tab", None);
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        open_link_tab_action.connect_activate(move |_, _| {
+            let window = ctx.borrow().window.clone();
+            let link = window.context_link.borrow().clone();
+            if let Some(url) = link.and_then(|link| ServoUrl::parse(&link).ok()) {
+                open_browser(&window, &servo, url, Opener::User);
+            }
+        });
+    }
+
+    let select_all_action = gio::SimpleAction::new("select-all", None);
+    let copy_link_action = gio::SimpleAction::new("copy-link", None);
+    let open_link_action = gio::SimpleAction::new("open-link", None);
+
+    {
+        let servo = servo.clone();
+        select_all_action.connect_activate(move |_, _| {
+            servo.borrow_mut().handle_events(keymap::shortcut_events('a', Key::A));
+        });
+    }
+
+    {
+        let ctx = context.clone();
+        copy_link_action.connect_activate(move |_, _| {
+            if let Some(ref link) = *ctx.borrow().window.context_link.borrow() {
+                gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(link);
+            }
+        });
+    }
+
+    {
+        let ctx = context.clone();
+        open_link_action.connect_activate(move |_, _| {
+            let ctx = ctx.borrow();
+            if let Some(ref link) = *ctx.window.context_link.borrow() {
+                let screen = ctx.window.gtk_window.get_screen();
+                if let Err(e) = gtk::show_uri(screen.as_ref(), link, gtk::get_current_event_time()) {
+                    println!("failed to open {}: {}", link, e);
+                }
+            }
+        });
+    }
+
+    let focus_location_action = gio::SimpleAction::new("focus-location", None);
+
+    {
+        let location_entry = context.borrow().window.location_entry.clone();
+        focus_location_action.connect_activate(move |_, _| {
+            location_entry.grab_focus();
+        });
+    }
+
+    {
+        let servo = servo.clone();
+        let ctx = context.clone();
+        context.borrow().window.location_entry.connect_activate(move |entry| {
+            let text = entry.get_text().unwrap_or_default();
+            let text = text.trim();
+            let url = match ServoUrl::parse(text) {
+                Ok(url) => url,
+                Err(_) => match ServoUrl::parse(&format!("http://{}", text)) {
+                    Ok(url) => url,
+                    Err(_) => return,
+                },
+            };
+            if let Some(browser_id) = ctx.borrow().window.browser.get() {
+                servo.borrow_mut().handle_events(vec![WindowEvent::LoadUrl(browser_id, url)]);
+            }
+            ctx.borrow().window.gl_area.grab_focus();
+        });
+    }
+
+    context.borrow().window.gtk_window.add_action(&history_action);
     context.borrow().window.gtk_window.add_action(&back_action);
     context.borrow().window.gtk_window.add_action(&forward_action);
+    context.borrow().window.gtk_window.add_action(&reload_action);
+    context.borrow().window.gtk_window.add_action(&home_action);
+    context.borrow().window.gtk_window.add_action(&zoom_in_action);
+    context.borrow().window.gtk_window.add_action(&zoom_out_action);
+    context.borrow().window.gtk_window.add_action(&zoom_reset_action);
+    context.borrow().window.gtk_window.add_action(&copy_action);
+    context.borrow().window.gtk_window.add_action(&cut_action);
+    context.borrow().window.gtk_window.add_action(&paste_action);
+    context.borrow().window.gtk_window.add_action(&select_all_action);
+    context.borrow().window.gtk_window.add_action(&copy_link_action);
+    context.borrow().window.gtk_window.add_action(&open_link_action);
+    context.borrow().window.gtk_window.add_action(&open_link_tab_action);
+    context.borrow().window.gtk_window.add_action(&focus_location_action);
+    context.borrow().window.gtk_window.add_action(&log_in_action);
+    context.borrow().window.gtk_window.add_action(&log_out_action);
 
     {
         let context = context.borrow();
         *context.window.back_action.borrow_mut() = back_action;
         *context.window.forward_action.borrow_mut() = forward_action;
+        *context.window.history_action.borrow_mut() = history_action;
     }
 
     context.borrow_mut().servo = Some(servo);
 }
 
-//helpers
-fn to_mouse_button(gdk_button: u32) -> MouseButton {
-    match gdk_button as i32 {
-        GDK_BUTTON_PRIMARY => MouseButton::Left,
-        GDK_BUTTON_SECONDARY => MouseButton::Right,
-        GDK_BUTTON_MIDDLE => MouseButton::Middle,
-        _ => panic!("GDK mouse button click error"),
-    }
+//who asks for a new browsing context
+#[derive(Clone, Copy, PartialEq)]
+enum Opener {
+    //window.open or a target=_blank link, up to the popup policy
+    Page,
+    //Open Link in New Tab, always a tab
+    User,
 }
 
-fn to_key(gdk_key: gdk_key::Key) -> (Option<char>, Option<Key>) {
-    let unicode =
-        gdk::keyval_to_unicode(gdk_key).and_then(|ch| {
-            if ch.is_control() {
-                None
-            } else {
-                Some(ch)
-            }
-        });
-    let key = match gdk_key {
-        gdk_key::space => Key::Space,
-        gdk_key::apostrophe => Key::Apostrophe,
-        gdk_key::comma => Key::Comma,
-        gdk_key::minus => Key::Minus,
-        gdk_key::period => Key::Period,
-        gdk_key::slash => Key::Slash,
-        gdk_key::_0 => Key::Num0,
-        gdk_key::_1 => Key::Num1,
-        gdk_key::_2 => Key::Num2,
-        gdk_key::_3 => Key::Num3,
-        gdk_key::_4 => Key::Num4,
-        gdk_key::_5 => Key::Num5,
-        gdk_key::_6 => Key::Num6,
-        gdk_key::_7 => Key::Num7,
-        gdk_key::_8 => Key::Num8,
-        gdk_key::_9 => Key::Num9,
-        gdk_key::semicolon => Key::Semicolon,
-        gdk_key::equal => Key::Equal,
-        gdk_key::A | gdk_key::a => Key::A,
-        gdk_key::B | gdk_key::b => Key::B,
-        gdk_key::C | gdk_key::c => Key::C,
-        gdk_key::D | gdk_key::d => Key::D,
-        gdk_key::E | gdk_key::e => Key::E,
-        gdk_key::F | gdk_key::f => Key::F,
-        gdk_key::G | gdk_key::g => Key::G,
-        gdk_key::H | gdk_key::h => Key::H,
-        gdk_key::I | gdk_key::i => Key::I,
-        gdk_key::J | gdk_key::j => Key::J,
-        gdk_key::K | gdk_key::k => Key::K,
-        gdk_key::L | gdk_key::l => Key::L,
-        gdk_key::M | gdk_key::m => Key::M,
-        gdk_key::N | gdk_key::n => Key::N,
-        gdk_key::O | gdk_key::o => Key::O,
-        gdk_key::P | gdk_key::p => Key::P,
-        gdk_key::Q | gdk_key::q => Key::Q,
-        gdk_key::R | gdk_key::r => Key::R,
-        gdk_key::S | gdk_key::s => Key::S,
-        gdk_key::T | gdk_key::t => Key::T,
-        gdk_key::U | gdk_key::u => Key::U,
-        gdk_key::V | gdk_key::v => Key::V,
-        gdk_key::W | gdk_key::w => Key::W,
-        gdk_key::X | gdk_key::x => Key::X,
-        gdk_key::Y | gdk_key::y => Key::Y,
-        gdk_key::Z | gdk_key::z => Key::Z,
-        gdk_key::bracketleft => Key::LeftBracket,
-        gdk_key::backslash => Key::Backslash,
-        gdk_key::bracketright => Key::RightBracket,
-        gdk_key::dead_grave => Key::GraveAccent,
-        gdk_key::Escape => Key::Escape,
-        gdk_key::Return => Key::Enter,
-        gdk_key::Tab => Key::Tab,
-        gdk_key::BackSpace => Key::Backspace,
-        gdk_key::Insert => Key::Insert,
-        gdk_key::Delete => Key::Delete,
-        gdk_key::Right => Key::Right,
-        gdk_key::Left => Key::Left,
-        gdk_key::Down => Key::Down,
-        gdk_key::Up => Key::Up,
-        gdk_key::Page_Up => Key::PageUp,
-        gdk_key::Page_Down => Key::PageDown,
-        gdk_key::Home => Key::Home,
-        gdk_key::End => Key::End,
-        gdk_key::Caps_Lock => Key::CapsLock,
-        gdk_key::Scroll_Lock => Key::ScrollLock,
-        gdk_key::Num_Lock => Key::NumLock,
-        gdk_key::_3270_PrintScreen => Key::PrintScreen, // TODO
-        gdk_key::Pause => Key::Pause,
-        gdk_key::F1 => Key::F1,
-        gdk_key::F2 => Key::F2,
-        gdk_key::F3 => Key::F3,
-        gdk_key::F4 => Key::F4,
-        gdk_key::F5 => Key::F5,
-        gdk_key::F6 => Key::F6,
-        gdk_key::F7 => Key::F7,
-        gdk_key::F8 => Key::F8,
-        gdk_key::F9 => Key::F9,
-        gdk_key::F10 => Key::F10,
-        gdk_key::F11 => Key::F11,
-        gdk_key::F12 => Key::F12,
-        gdk_key::F13 => Key::F13,
-        gdk_key::F14 => Key::F14,
-        gdk_key::F15 => Key::F15,
-        gdk_key::F16 => Key::F16,
-        gdk_key::F17 => Key::F17,
-        gdk_key::F18 => Key::F18,
-        gdk_key::F19 => Key::F19,
-        gdk_key::F20 => Key::F20,
-        gdk_key::F21 => Key::F21,
-        gdk_key::F22 => Key::F22,
-        gdk_key::F23 => Key::F23,
-        gdk_key::F24 => Key::F24,
-        gdk_key::F25 => Key::F25,
-        gdk_key::KP_0 => Key::Kp0,
-        gdk_key::KP_1 => Key::Kp1,
-        gdk_key::KP_2 => Key::Kp2,
-        gdk_key::KP_3 => Key::Kp3,
-        gdk_key::KP_4 => Key::Kp4,
-        gdk_key::KP_5 => Key::Kp5,
-        gdk_key::KP_6 => Key::Kp6,
-        gdk_key::KP_7 => Key::Kp7,
-        gdk_key::KP_8 => Key::Kp8,
-        gdk_key::KP_9 => Key::Kp9,
-        gdk_key::KP_Decimal => Key::KpDecimal,
-        gdk_key::KP_Divide => Key::KpDivide,
-        gdk_key::KP_Multiply => Key::KpMultiply,
-        gdk_key::KP_Subtract => Key::KpSubtract,
-        gdk_key::KP_Add => Key::KpAdd,
-        gdk_key::KP_Enter => Key::KpEnter,
-        gdk_key::KP_Equal => Key::KpEqual,
-        gdk_key::Shift_L => Key::LeftShift,
-        gdk_key::Control_L => Key::LeftControl,
-        gdk_key::Alt_L => Key::LeftAlt,
-        gdk_key::Super_L => Key::LeftSuper,
-        gdk_key::Shift_R => Key::RightShift,
-        gdk_key::Control_R => Key::RightControl,
-        gdk_key::Alt_R => Key::RightAlt,
-        gdk_key::Super_R => Key::RightSuper,
-        gdk_key::Menu => Key::Menu,
-        //gdk_key:: => Key::World1, // TODO
-        //gdk_key:: => Key::World2, // TODO
-        //gdk_key:: => Key::NavigateBackward, // TODO
-        //gdk_key:: => Key::NavigateForward, // TODO
-        _ => return (None, None)
+//opens a secondary browsing context as a tab of this window, as the popup policy allows
+//for pages. the policy is not asked when the user opens the tab
+fn open_browser(window: &Rc<Window>, servo: &Rc<RefCell<servo::Servo<Window>>>, url: ServoUrl, opener: Opener) {
+    let (policy, max_tabs) = {
+        let profile = window.profile.borrow();
+        (profile.popup_policy(), profile.max_tabs())
+    };
+    let open_in_current = opener == Opener::Page && match policy {
+        PopupPolicy::Block => {
+            println!("popup blocked: {}", url);
+            return;
+        },
+        PopupPolicy::Current => true,
+        PopupPolicy::Tab => window.tabs.borrow().len() >= max_tabs,
     };
-    (unicode, Some(key))
+    if open_in_current {
+        if let Some(browser_id) = window.browser.get() {
+            servo.borrow_mut().handle_events(vec![WindowEvent::LoadUrl(browser_id, url)]);
+        }
+        return;
+    }
+
+    let label = url.host_str().unwrap_or("New tab").to_string();
+    let (sender, receiver) = ipc::channel().unwrap();
+    servo.borrow_mut().handle_events(vec![WindowEvent::NewBrowser(url, sender)]);
+    let browser_id = match receiver.recv() {
+        Ok(browser_id) => browser_id,
+        Err(e) => {
+            println!("failed to open a browser: {:?}", e);
+            return;
+        },
+    };
+
+    let tab = window.add_tab(browser_id, &label);
+    {
+        let window = window.clone();
+        let servo = servo.clone();
+        tab.button.connect_clicked(move |_| {
+            select_browser(&window, &servo, browser_id);
+        });
+    }
+    {
+        let close_button = gtk::Button::new_from_icon_name("window-close-symbolic", gtk::IconSize::Menu.into());
+        close_button.set_relief(gtk::ReliefStyle::None);
+        close_button.set_tooltip_text(Some("Close tab"));
+        tab.container.pack_start(&close_button, false, false, 0);
+        close_button.show();
+        let window = window.clone();
+        let servo = servo.clone();
+        close_button.connect_clicked(move |_| {
+            close_browser(&window, &servo, browser_id);
+        });
+    }
+    window.tabs.borrow_mut().push(tab);
+    window.tab_bar.set_visible(true);
+    select_browser(window, servo, browser_id);
 }
 
-fn to_modifier(mods: gdk::ModifierType) -> KeyModifiers {
-    let mut key_mods = KeyModifiers::empty();
-    if mods.contains(gdk::META_MASK) {
-        key_mods.insert(KeyModifiers::ALT);
+fn select_browser(window: &Rc<Window>, servo: &Rc<RefCell<servo::Servo<Window>>>, browser_id: BrowserId) {
+    window.browser.set(Some(browser_id));
+    *window.hovered_link.borrow_mut() = None;
+    servo.borrow_mut().handle_events(vec![WindowEvent::SelectBrowser(browser_id)]);
+    let history = window.histories.borrow().get(&browser_id).cloned();
+    if let Some((entries, current)) = history {
+        window.show_history(entries, current);
     }
-    if mods.contains(gdk::SUPER_MASK) {
-        key_mods.insert(KeyModifiers::SUPER);
+}
+
+//closes a secondary browsing context and returns to the login page
+fn close_browser(window: &Rc<Window>, servo: &Rc<RefCell<servo::Servo<Window>>>, browser_id: BrowserId) {
+    let main_browser = match window.main_browser.get() {
+        Some(main_browser) if main_browser != browser_id => main_browser,
+        _ => return,
+    };
+    if window.browser.get() == Some(browser_id) {
+        select_browser(window, servo, main_browser);
     }
-    if mods.contains(gdk::CONTROL_MASK) {
-        key_mods.insert(KeyModifiers::CONTROL);
+    servo.borrow_mut().handle_events(vec![WindowEvent::CloseBrowser(browser_id)]);
+    window.histories.borrow_mut().remove(&browser_id);
+
+    let mut tabs = window.tabs.borrow_mut();
+    if let Some(pos) = tabs.iter().position(|tab| tab.id == browser_id) {
+        let tab = tabs.remove(pos);
+        window.tab_bar.remove(&tab.container);
     }
-    if mods.contains(gdk::SHIFT_MASK) {
-        key_mods.insert(KeyModifiers::SHIFT);
+    window.tab_bar.set_visible(tabs.len() > 1);
+}
+
+//helpers
+//returns the touch sequence identifier and the position of a touch event
+fn touch_details(event: &gdk::Event) -> (usize, f64, f64) {
+    unsafe {
+        let raw: *const gdk_sys::GdkEvent = event.to_glib_none().0;
+        let touch = &*(raw as *const gdk_sys::GdkEventTouch);
+        (touch.sequence as usize, touch.x, touch.y)
     }
-    key_mods
 }
 
+//types the text of a clipboard selection into the page. the text is requested without
+//waiting, wait_for_text runs a nested main loop whose callbacks would find servo borrowed
+fn paste(window: &Rc<Window>, selection: &gdk::Atom) {
+    let window = window.clone();
+    gtk::Clipboard::get(selection).request_text(move |_, text| {
+        if let Some(text) = text {
+            for event in keymap::text_events(&text) {
+                window.queue_event(event);
+            }
+        }
+    });
+}
+
+fn to_mouse_button(gdk_button: u32) -> Option<MouseButton> {
+    match gdk_button as i32 {
+        GDK_BUTTON_PRIMARY => Some(MouseButton::Left),
+        GDK_BUTTON_SECONDARY => Some(MouseButton::Right),
+        GDK_BUTTON_MIDDLE => Some(MouseButton::Middle),
+        _ => None,
+    }
+}
@@ -0,0 +1,120 @@
+extern crate cafcon;
+extern crate futures;
+extern crate tokio_core;
+
+mod mock_portal;
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::thread;
+use std::time::Duration;
+use futures::{future, Future};
+use tokio_core::reactor::{Core, Handle};
+use cafcon::interface::Family;
+use cafcon::probe::{self, Connectivity, HttpProber, Prober, Route};
+use cafcon::profile::PortalRule;
+use mock_portal::{Behaviour, MockPortal};
+
+//a resolver given keeps system proxies out of the way, and the portal's address is literal
+//so it is only asked about the DNS hijacking names
+fn local_route() -> Route {
+    Route {
+        interface: None,
+        dns_servers: vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))],
+    }
+}
+
+fn detect(prober: &Prober, test_url: &str, rule: PortalRule) -> Result<String, String> {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    core.run(probe::detect_on(prober, test_url, vec![local_route()], rule, &handle))
+        .map(|detection| detection.auth_url)
+        .map_err(|failure| failure.message)
+}
+
+fn is_online(test_url: &str) -> bool {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    core.run(probe::is_online(&HttpProber, test_url, &local_route(), PortalRule::Any, &handle)).unwrap()
+}
+
+#[test]
+fn open_network_has_no_portal() {
+    let portal = MockPortal::start(Behaviour::Open);
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    match core.run(probe::detect_on(&HttpProber, &portal.check_url(), vec![local_route()], PortalRule::Any, &handle)) {
+        Err(failure) => assert!(failure.online, "{}", failure.message),
+        Ok(detection) => panic!("found a portal at {}", detection.auth_url),
+    }
+    assert!(is_online(&portal.check_url()));
+}
+
+#[test]
+fn redirect_leads_to_login_page() {
+    let portal = MockPortal::start(Behaviour::Redirect);
+    assert_eq!(detect(&HttpProber, &portal.check_url(), PortalRule::Any), Ok(portal.login_url()));
+    assert!(!is_online(&portal.check_url()));
+}
+
+#[test]
+fn meta_refresh_leads_to_login_page() {
+    let portal = MockPortal::start(Behaviour::MetaRefresh);
+    assert_eq!(detect(&HttpProber, &portal.check_url(), PortalRule::Any), Ok(portal.login_url()));
+}
+
+#[test]
+fn wispr_leads_to_login_page() {
+    let portal = MockPortal::start(Behaviour::Wispr);
+    assert_eq!(detect(&HttpProber, &portal.check_url(), PortalRule::Any), Ok(portal.login_url()));
+}
+
+#[test]
+fn captive_api_status_is_followed() {
+    let portal = MockPortal::start(Behaviour::CaptiveApi);
+    assert_eq!(detect(&HttpProber, &portal.check_url(), PortalRule::Any), Ok(portal.login_url()));
+
+    portal.log_in();
+    assert!(is_online(&portal.check_url()));
+}
+
+#[test]
+fn login_is_noticed_until_the_session_expires() {
+    let portal = MockPortal::start_with_session(Behaviour::Redirect, Some(Duration::from_millis(500)));
+    assert!(!is_online(&portal.check_url()));
+
+    portal.log_in();
+    assert!(is_online(&portal.check_url()));
+
+    thread::sleep(Duration::from_millis(600));
+    assert!(!is_online(&portal.check_url()));
+}
+
+//answers for each family without touching the network
+struct FixedProber {
+    ipv4: Connectivity,
+    ipv6: Connectivity,
+}
+
+impl Prober for FixedProber {
+    fn probe(&self, _test_url: &str, _route: &Route, family: Family, _handle: &Handle)
+             -> Box<Future<Item=Connectivity, Error=()>> {
+        let result = match family {
+            Family::Ipv4 => self.ipv4.clone(),
+            Family::Ipv6 => self.ipv6.clone(),
+        };
+        Box::new(future::ok(result))
+    }
+}
+
+#[test]
+fn rule_decides_between_families() {
+    let prober = FixedProber {
+        ipv4: Connectivity::Portal("http://portal.example.com/".to_string()),
+        ipv6: Connectivity::Online,
+    };
+    let test_url = "http://check.example.com/generate_204";
+    assert_eq!(detect(&prober, test_url, PortalRule::Any), Ok("http://portal.example.com/".to_string()));
+    assert_eq!(detect(&prober, test_url, PortalRule::Ipv4), Ok("http://portal.example.com/".to_string()));
+    assert!(detect(&prober, test_url, PortalRule::Ipv6).is_err());
+    assert!(detect(&prober, test_url, PortalRule::All).is_err());
+}
@@ -0,0 +1,143 @@
+//a captive portal on localhost that answers the check URL the way real ones do
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Behaviour {
+    //no portal, the check gets its 204
+    Open,
+    //302 to the login page, given relative to the portal
+    Redirect,
+    //200 with a page that refreshes to the login page
+    MetaRefresh,
+    //200 with an RFC 8908 status
+    CaptiveApi,
+    //200 with a WISPr block naming the login page
+    Wispr,
+}
+
+struct State {
+    behaviour: Behaviour,
+    //how long a login lasts, forever when not given
+    session: Option<Duration>,
+    logged_in: Option<Instant>,
+}
+
+impl State {
+    fn is_open(&self) -> bool {
+        if self.behaviour == Behaviour::Open {
+            return true;
+        }
+        match (self.logged_in, self.session) {
+            (Some(since), Some(session)) => since.elapsed() < session,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+pub struct MockPortal {
+    port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockPortal {
+    pub fn start(behaviour: Behaviour) -> MockPortal {
+        MockPortal::start_with_session(behaviour, None)
+    }
+
+    pub fn start_with_session(behaviour: Behaviour, session: Option<Duration>) -> MockPortal {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("cannot bind the mock portal");
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State {
+            behaviour: behaviour,
+            session: session,
+            logged_in: None,
+        }));
+
+        {
+            let state = state.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    let state = state.clone();
+                    thread::spawn(move || serve(stream, port, &state));
+                }
+            });
+        }
+
+        MockPortal {
+            port: port,
+            state: state,
+        }
+    }
+
+    pub fn check_url(&self) -> String {
+        format!("http://127.0.0.1:{}/generate_204", self.port)
+    }
+
+    pub fn login_url(&self) -> String {
+        format!("http://127.0.0.1:{}/login?ap=mock", self.port)
+    }
+
+    //what the user accepting the terms on the login page would do
+    pub fn log_in(&self) {
+        self.state.lock().unwrap().logged_in = Some(Instant::now());
+    }
+}
+
+fn serve(mut stream: TcpStream, port: u16, state: &Mutex<State>) {
+    let mut request = vec![];
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(len) => request.extend_from_slice(&buf[..len]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request).into_owned();
+    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+    let login_url = format!("http://127.0.0.1:{}/login?ap=mock", port);
+    let (open, behaviour) = {
+        let state = state.lock().unwrap();
+        (state.is_open(), state.behaviour)
+    };
+
+    let response = if path.starts_with("/login") {
+        respond("200 OK", "text/html", "<html><body><form method=\"post\"><button>Accept</button></form></body></html>")
+    } else if behaviour == Behaviour::CaptiveApi {
+        let status = if open {
+            "{\"captive\": false}".to_string()
+        } else {
+            format!("{{\"captive\": true, \"user-portal-url\": \"{}\", \"seconds-remaining\": 0}}", login_url)
+        };
+        respond("200 OK", "application/captive+json", &status)
+    } else if open {
+        "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_string()
+    } else {
+        match behaviour {
+            Behaviour::Redirect => "HTTP/1.1 302 Found\r\nLocation: /login?ap=mock\r\nConnection: close\r\n\r\n".to_string(),
+            Behaviour::MetaRefresh => respond("200 OK", "text/html", &format!(
+                "<html><head><META HTTP-EQUIV=\"Refresh\" CONTENT=\"0; URL={}\"></head></html>", login_url)),
+            Behaviour::Wispr => respond("200 OK", "text/html", &format!(
+                "<html><!--<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                 <WISPAccessGatewayParam><Redirect><MessageType>100</MessageType><ResponseCode>0</ResponseCode>\
+                 <LoginURL>{}</LoginURL></Redirect></WISPAccessGatewayParam>--></html>",
+                login_url.replace("&", "&amp;"))),
+            Behaviour::Open | Behaviour::CaptiveApi => unreachable!(),
+        }
+    };
+    stream.write_all(response.as_bytes()).ok();
+}
+
+fn respond(status: &str, content_type: &str, body: &str) -> String {
+    format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, content_type, body.len(), body)
+}
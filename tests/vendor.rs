@@ -0,0 +1,106 @@
+extern crate cafcon;
+extern crate hyper;
+
+use hyper::Url;
+use cafcon::adapter::{self, Credentials};
+use cafcon::vendor::{self, Vendor};
+
+fn vendor_of(auth_url: &str, page: Option<&str>) -> Option<Vendor> {
+    vendor::fingerprint(auth_url, page).map(|fingerprint| fingerprint.vendor)
+}
+
+#[test]
+fn redirect_urls_give_the_product_away() {
+    assert_eq!(vendor_of("http://10.1.0.1:3990/prelogin?res=notyet&uamip=10.1.0.1&uamport=3990&challenge=00ff", None),
+               Some(Vendor::CoovaChilli));
+    assert_eq!(vendor_of("https://1.1.1.1/login.html?switch_url=https://1.1.1.1/login.html&ap_mac=00:11:22:33:44:55&wlan=Guest", None),
+               Some(Vendor::CiscoWlc));
+    assert_eq!(vendor_of("https://ise.example.com:8443/portal/gateway?sessionId=0a0b0c&portal=1234&action=cwa", None),
+               Some(Vendor::CiscoIse));
+    assert_eq!(vendor_of("https://securelogin.arubanetworks.com/cgi-bin/login?cmd=login&mac=00:11:22:33:44:55&essid=Guest", None),
+               Some(Vendor::ArubaClearPass));
+    assert_eq!(vendor_of("http://192.168.1.2:8880/guest/s/default/?ap=00:11:22:33:44:55&id=66:77:88:99:aa:bb&t=1&ssid=Guest", None),
+               Some(Vendor::Unifi));
+    assert_eq!(vendor_of("https://n123.network-auth.com/splash/?mac=00:11:22:33:44:55&continue_url=http%3A%2F%2Fexample.com%2F", None),
+               Some(Vendor::Meraki));
+    assert_eq!(vendor_of("http://192.168.1.1:8002/index.php?zone=guest&redirurl=http%3A%2F%2Fexample.com%2F", None),
+               Some(Vendor::PfSense));
+    assert_eq!(vendor_of("http://192.168.1.1:2050/opennds_preauth/?fas=abcd", None), Some(Vendor::OpenNds));
+    assert_eq!(vendor_of("http://192.168.1.99:1000/fgtauth?0123456789abcdef", None), Some(Vendor::Fortinet));
+}
+
+#[test]
+fn page_markers_settle_generic_addresses() {
+    let page = "<form name=\"sendin\" action=\"http://hotspot.lan/login\" method=\"post\">\
+                <script>document.sendin.password.value = hexMD5('\\011' + document.login.password.value + '\\101\\102');</script>";
+    assert_eq!(vendor_of("http://hotspot.lan/login", None), None);
+    assert_eq!(vendor_of("http://hotspot.lan/login.html?dst=http%3A%2F%2Fexample.com%2F", None), None);
+    assert_eq!(vendor_of("http://hotspot.lan/login", Some(page)), Some(Vendor::Mikrotik));
+    assert!(vendor::fingerprint("http://hotspot.lan/login", Some(page)).unwrap().page_confirmed);
+    assert!(!vendor::fingerprint("http://192.168.1.1:8002/index.php?zone=guest", None).unwrap().page_confirmed);
+}
+
+#[test]
+fn unknown_portals_are_not_guessed() {
+    assert_eq!(vendor_of("http://portal.example.com/welcome", Some("<html><body>Welcome</body></html>")), None);
+    assert_eq!(vendor_of("http://portal.example.com/?id=1", None), None);
+}
+
+#[test]
+fn adapters_build_the_product_requests() {
+    let credentials = Credentials {
+        username: "guest".to_string(),
+        password: "secret".to_string(),
+    };
+
+    let url = Url::parse("http://192.168.1.1:8002/index.php?zone=guest&redirurl=http%3A%2F%2Fexample.com%2F").unwrap();
+    let login = adapter::adapter_for(Vendor::PfSense, &url, "").login(&credentials).unwrap();
+    assert_eq!(login.method, "POST");
+    assert_eq!(login.url, "http://192.168.1.1:8002/index.php?zone=guest");
+    assert!(login.form.contains(&("auth_user".to_string(), "guest".to_string())));
+    assert!(login.form.contains(&("auth_pass".to_string(), "secret".to_string())));
+
+    //CHAP over the challenge from the redirect, md5 of 0x00 + "secret" + 0x00ff
+    let url = Url::parse("http://10.1.0.1:3990/prelogin?res=notyet&uamip=10.1.0.1&uamport=3990&challenge=00ff").unwrap();
+    let login = adapter::adapter_for(Vendor::CoovaChilli, &url, "").login(&credentials).unwrap();
+    assert_eq!(login.url, "http://10.1.0.1:3990/logon");
+    assert!(login.form.contains(&("response".to_string(), "8180708413f251e42c8093b8333167f9".to_string())));
+
+    let url = Url::parse("http://192.168.1.99:1000/fgtauth?0123456789abcdef").unwrap();
+    let page = "<input type=\"hidden\" name=\"magic\" value=\"0123456789abcdef\"><input type=\"hidden\" name=\"4Tredir\" value=\"http://example.com/\">";
+    let login = adapter::adapter_for(Vendor::Fortinet, &url, page).login(&credentials).unwrap();
+    assert_eq!(login.url, "http://192.168.1.99:1000/");
+    assert!(login.form.contains(&("magic".to_string(), "0123456789abcdef".to_string())));
+    assert!(login.form.contains(&("4Tredir".to_string(), "http://example.com/".to_string())));
+
+    let url = Url::parse("https://ise.example.com:8443/portal/gateway?sessionId=0a0b0c").unwrap();
+    assert!(adapter::adapter_for(Vendor::CiscoIse, &url, "").login(&credentials).is_none());
+}
+
+#[test]
+fn credentials_only_go_to_the_login_page_host() {
+    let credentials = Credentials {
+        username: "guest".to_string(),
+        password: "secret".to_string(),
+    };
+
+    let url = Url::parse("https://1.1.1.1/login.html?switch_url=https://1.1.1.1/login.html&wlan=Guest").unwrap();
+    let wlc = adapter::adapter_for(Vendor::CiscoWlc, &url, "");
+    assert!(adapter::login_request(&*wlc, &credentials, "1.1.1.1").is_ok());
+
+    //a redirect naming another host to post to is not followed with the password
+    let url = Url::parse("https://1.1.1.1/login.html?switch_url=https://attacker.example.com/login.html").unwrap();
+    let wlc = adapter::adapter_for(Vendor::CiscoWlc, &url, "");
+    assert!(adapter::login_request(&*wlc, &credentials, "1.1.1.1").is_err());
+
+    let url = Url::parse("https://portal.example.com/?switchip=attacker.example.com&url=http://example.com/").unwrap();
+    let aruba = adapter::adapter_for(Vendor::ArubaClearPass, &url, "");
+    assert!(adapter::login_request(&*aruba, &credentials, "portal.example.com").is_err());
+}
+
+#[test]
+fn pfsense_zones_are_escaped() {
+    let url = Url::parse("http://192.168.1.1:8002/index.php?zone=guest%26accept%3DLogin").unwrap();
+    let login = adapter::adapter_for(Vendor::PfSense, &url, "").login(&Credentials::default()).unwrap();
+    assert_eq!(login.url, "http://192.168.1.1:8002/index.php?zone=guest%26accept%3DLogin");
+}
@@ -0,0 +1,173 @@
+//find in page for long terms of service pages. servo has no find of its own and the
+//window cannot reach into the page, so Ctrl+F opens a bar inside the page itself.
+//servo runs this in every document as soon as it has a head
+(function() {
+    if (window.__cafconFind) {
+        return;
+    }
+    window.__cafconFind = true;
+
+    var SHOW_TEXT = 4;
+    var BAR_STYLE = "position: fixed; top: 0; right: 0; z-index: 2147483647; display: block; " +
+        "padding: 4px 6px; background: #f6f5f4; color: #2e3436; border: 1px solid #cdc7c2; " +
+        "font: 13px sans-serif;";
+    var MARK_STYLE = "background: #fce94f; color: #000;";
+    var SELECTED_STYLE = "background: #f57900; color: #000;";
+
+    var bar = null;
+    var input = null;
+    var caseBox = null;
+    var count = null;
+    var marks = [];
+    var selected = -1;
+
+    function clear() {
+        marks.forEach(function(mark) {
+            var parent = mark.parentNode;
+            if (parent) {
+                parent.replaceChild(document.createTextNode(mark.textContent), mark);
+                parent.normalize();
+            }
+        });
+        marks = [];
+        selected = -1;
+    }
+
+    function searchable(node) {
+        var tag = node.parentNode ? node.parentNode.nodeName : "";
+        return tag !== "SCRIPT" && tag !== "STYLE" && tag !== "NOSCRIPT" && tag !== "TEXTAREA" &&
+            !bar.contains(node);
+    }
+
+    //wraps every match in a mark, the text nodes are collected first as wrapping
+    //splits them under the walker
+    function search() {
+        clear();
+        var query = input.value;
+        if (query && document.body) {
+            var caseSensitive = caseBox.checked;
+            var needle = caseSensitive ? query : query.toLowerCase();
+            var walker = document.createTreeWalker(document.body, SHOW_TEXT, null, false);
+            var nodes = [];
+            while (walker.nextNode()) {
+                if (searchable(walker.currentNode)) {
+                    nodes.push(walker.currentNode);
+                }
+            }
+            nodes.forEach(function(node) {
+                var text = caseSensitive ? node.nodeValue : node.nodeValue.toLowerCase();
+                var pos = text.indexOf(needle);
+                while (pos !== -1) {
+                    var match = node.splitText(pos);
+                    node = match.splitText(needle.length);
+                    var mark = document.createElement("mark");
+                    mark.setAttribute("style", MARK_STYLE);
+                    match.parentNode.replaceChild(mark, match);
+                    mark.appendChild(match);
+                    marks.push(mark);
+                    text = caseSensitive ? node.nodeValue : node.nodeValue.toLowerCase();
+                    pos = text.indexOf(needle);
+                }
+            });
+        }
+        select(marks.length > 0 ? 0 : -1);
+    }
+
+    function select(index) {
+        if (selected >= 0) {
+            marks[selected].setAttribute("style", MARK_STYLE);
+        }
+        selected = index;
+        if (selected >= 0) {
+            var mark = marks[selected];
+            mark.setAttribute("style", SELECTED_STYLE);
+            var rect = mark.getBoundingClientRect();
+            window.scrollTo(window.pageXOffset, window.pageYOffset + rect.top - window.innerHeight / 3);
+            count.textContent = (selected + 1) + " of " + marks.length;
+        } else {
+            count.textContent = input.value ? "No matches" : "";
+        }
+    }
+
+    function step(delta) {
+        if (marks.length > 0) {
+            select((selected + delta + marks.length) % marks.length);
+        }
+    }
+
+    function button(label, title, action) {
+        var element = document.createElement("button");
+        element.textContent = label;
+        element.title = title;
+        element.addEventListener("click", function(event) {
+            event.preventDefault();
+            action();
+        });
+        return element;
+    }
+
+    function build() {
+        bar = document.createElement("div");
+        bar.setAttribute("style", BAR_STYLE);
+
+        input = document.createElement("input");
+        input.type = "text";
+        input.placeholder = "Find in page";
+        input.addEventListener("input", search);
+        //Enter goes to the next match, Shift+Enter to the previous one
+        input.addEventListener("keydown", function(event) {
+            if (event.key === "Enter") {
+                event.preventDefault();
+                step(event.shiftKey ? -1 : 1);
+            }
+        });
+
+        caseBox = document.createElement("input");
+        caseBox.type = "checkbox";
+        caseBox.addEventListener("change", search);
+        var caseLabel = document.createElement("label");
+        caseLabel.appendChild(caseBox);
+        caseLabel.appendChild(document.createTextNode(" Match case "));
+
+        count = document.createElement("span");
+
+        bar.appendChild(input);
+        bar.appendChild(button("↑", "Previous match", function() { step(-1); }));
+        bar.appendChild(button("↓", "Next match", function() { step(1); }));
+        bar.appendChild(caseLabel);
+        bar.appendChild(count);
+        //Escape quits the application before the page sees it
+        bar.appendChild(button("×", "Close the find bar", close));
+        document.body.appendChild(bar);
+    }
+
+    function open() {
+        if (!document.body) {
+            return;
+        }
+        if (!bar) {
+            build();
+        }
+        bar.style.display = "block";
+        input.focus();
+        if (input.value) {
+            search();
+        }
+    }
+
+    function close() {
+        clear();
+        count.textContent = "";
+        bar.style.display = "none";
+    }
+
+    document.addEventListener("keydown", function(event) {
+        if (event.ctrlKey && !event.altKey && (event.key === "f" || event.key === "F")) {
+            event.preventDefault();
+            open();
+        } else if (event.key === "F3" && bar && bar.style.display !== "none") {
+            event.preventDefault();
+            step(event.shiftKey ? -1 : 1);
+        }
+    }, true);
+})();
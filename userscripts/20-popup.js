@@ -0,0 +1,37 @@
+//this servo neither implements window.open nor opens links in new browsing contexts, so
+//both are handed to the window by navigating the top document to cafcon-popup:. the
+//window refuses that navigation and opens the url as the popup policy says
+(function() {
+    if (window.__cafconPopup) {
+        return;
+    }
+    window.__cafconPopup = true;
+
+    function request(url) {
+        var target = new URL(url, document.baseURI).href;
+        window.top.location.href = "cafcon-popup:?url=" + encodeURIComponent(target);
+    }
+
+    //no window is handed back, portals that script the popup get null as from a blocker
+    window.open = function(url) {
+        if (url) {
+            request(String(url));
+        }
+        return null;
+    };
+
+    document.addEventListener("click", function(event) {
+        if (event.defaultPrevented || event.button !== 0) {
+            return;
+        }
+        var node = event.target;
+        while (node && node.nodeName !== "A") {
+            node = node.parentNode;
+        }
+        if (!node || !node.href || node.target !== "_blank") {
+            return;
+        }
+        event.preventDefault();
+        request(node.href);
+    }, false);
+})();